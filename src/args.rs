@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -14,5 +16,89 @@ pub(crate) enum Opt {
         /// Points to configuration file
         #[structopt(short, long, default_value = ".blinc")]
         config: String,
+
+        /// Runs only tasks with specified tag (can be repeated)
+        #[structopt(long)]
+        tag: Vec<String>,
+
+        /// Profile available in tasks as `${profile}` variable
+        #[structopt(short, long, value_name = "name", default_value = "dev")]
+        profile: String,
+
+        /// Arguments passed to tasks with `accepts_args` or `{args}` placeholder
+        #[structopt(last = true)]
+        args: Vec<String>,
+
+        #[structopt(subcommand)]
+        cmd: Option<Cmd>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub(crate) enum Cmd {
+    /// Runs only specified tasks and tasks they need
+    Run {
+        /// Names of tasks to run
+        #[structopt(required = true)]
+        tasks: Vec<String>,
+
+        /// Arguments passed to tasks with `accepts_args` or `{args}` placeholder
+        #[structopt(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Runs single command instead of configured tasks
+    #[structopt(setting = AppSettings::TrailingVarArg, setting = AppSettings::AllowLeadingHyphen)]
+    Exec {
+        /// Command to run
+        cmd: String,
+
+        /// Arguments of the command
+        #[structopt(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Runs tasks whenever files in the workspace change
+    Watch {
+        /// Runs tasks on lines written to named pipe instead of watching files
+        #[structopt(long, value_name = "path", conflicts_with = "socket")]
+        fifo: Option<PathBuf>,
+
+        /// Runs tasks on lines sent to Unix socket instead of watching files
+        #[structopt(long, value_name = "path")]
+        socket: Option<PathBuf>,
+
+        /// Accepts acknowledgements sent with `cargo blinc ack` on Unix socket
+        #[structopt(long, value_name = "path")]
+        ack_socket: Option<PathBuf>,
+    },
+
+    /// Runs tasks periodically, on schedule from arguments or `[schedule]` section
+    Schedule {
+        /// Interval between runs, e.g. 15m
+        #[structopt(long, value_name = "interval", conflicts_with = "cron")]
+        every: Option<String>,
+
+        /// Cron expression with seconds, e.g. "0 0 2 * * *"
+        #[structopt(long, value_name = "expression")]
+        cron: Option<String>,
+
+        /// Accepts acknowledgements sent with `cargo blinc ack` on Unix socket
+        #[structopt(long, value_name = "path")]
+        ack_socket: Option<PathBuf>,
+    },
+
+    /// Runs tasks on requests received on Unix socket
+    Daemon {
+        /// Path of the socket
+        #[structopt(long, default_value = ".blinc.sock")]
+        socket: PathBuf,
+    },
+
+    /// Acknowledges the result shown by the daemon, or turns the light off when no daemon runs
+    Ack {
+        /// Path of the daemon socket
+        #[structopt(long, default_value = ".blinc.sock")]
+        socket: PathBuf,
     },
 }
@@ -1,62 +1,557 @@
+use crate::cancel::Cancel;
 use crate::config::Config;
-use anyhow::Result;
+use crate::daemon;
+use crate::environment;
+use crate::error::NOT_STARTED;
+use crate::hooks::Hook;
+use crate::light::{Light, Notifier, State};
+use crate::report::{Outcome, Report, Status, TaskReport};
+use crate::schedule::Schedule;
+use crate::task::{Exit, Task};
+use crate::vars::{self, Vars};
+use crate::watch::{Source, Watcher};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
 use log::debug;
 use std::env;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::process;
-use transition::Transition;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL: Duration = Duration::from_secs(1);
+
+const CLOCK_CHECK: Duration = Duration::from_secs(60);
 
 pub(crate) struct Blinc {
     config: Config,
+    vars: Vars,
+    cancel: Cancel,
+    fresh: Arc<AtomicBool>,
 }
 
 impl Blinc {
-    pub(crate) fn new(config: Config) -> Self {
-        Blinc::init(&config);
-        Self { config }
+    pub(crate) fn new(config: Config) -> Result<Self> {
+        let vars = config.vars()?;
+        Blinc::init(&config, &vars)?;
+        Ok(Self {
+            config,
+            vars,
+            cancel: Cancel::default(),
+            fresh: Arc::default(),
+        })
     }
 
-    pub(crate) fn init(config: &Config) {
+    /// Sets up environment variables from `env_files`, `env_from_command` and `[env]` section, in
+    /// that order.
+    pub(crate) fn init(config: &Config, vars: &Vars) -> Result<()> {
+        for file in config.env_files() {
+            let path = config.dir()?.join(vars.expand(file)?);
+            for (k, v) in environment::from_file(path)? {
+                debug!("setting {} from env file", k);
+                env::set_var(k, v);
+            }
+        }
+        if let Some(command) = config.env_from_command() {
+            for (k, v) in environment::from_command(&vars.expand(command)?)? {
+                debug!("setting {} from env command", k);
+                match v {
+                    Some(v) => env::set_var(k, v),
+                    None => env::remove_var(k),
+                }
+            }
+        }
         if let Some(env) = config.env() {
             debug!("setting up env variables");
             for (k, v) in env {
-                debug!("setting {} = {}", k, v);
-                env::set_var(k, v);
+                debug!("setting {} = {:?}", k, v);
+                env::set_var(k, v.resolve(vars)?);
             }
         } else {
             debug!("no env variables to set");
         }
+        Ok(())
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Token cancelling the running tasks.
+    pub(crate) fn cancel(&self) -> &Cancel {
+        &self.cancel
     }
 
     pub(crate) fn exec_tasks(&self) -> Result<()> {
-        let tx = transition(&self.config)?.start()?;
+        self.warn_about_long_running_options();
+        let light = Light::start(&self.config)?;
+        let report = match self.run_tasks() {
+            Ok(report) => report,
+            Err(e) => {
+                light.finish(Outcome::Failure)?;
+                return Err(e);
+            }
+        };
+        println!("{report}");
+        let outcome = report.outcome();
+        light.finish(outcome)?;
+        if outcome == Outcome::Failure {
+            debug!("tasks failed, exiting with {}", report.code());
+            process::exit(report.code());
+        }
+        Ok(())
+    }
+
+    /// Runs the tasks whenever watched files change, holding the device open between runs. Only
+    /// tasks watching changed files run again, other tasks keep their previous results. Run still
+    /// in progress when files change is cancelled and started again.
+    pub(crate) fn watch(&self, source: &Source, ack_socket: Option<&Path>) -> Result<()> {
+        let root = self.vars.workspace_dir().canonicalize()?;
+        let watcher = Watcher::new(&root, self.config.watch(), source)?;
+        let light = Light::start(&self.config)?;
+        let mut previous = Report::default();
+        let mut changed: Option<Vec<PathBuf>> = None;
+        // changes of files only trigger runs when they come from the source
+        if !matches!(source, Source::Files) {
+            self.track_staleness(&light)?;
+        }
+        accept_acks(&light, ack_socket)?;
+        loop {
+            let selected = self.watching_tasks(changed.as_deref(), &root)?;
+            if selected.is_empty() {
+                debug!("no task watches changed files");
+                changed = watcher.changes()?;
+                continue;
+            }
+            self.set_fresh(false);
+            light.show(State::Pending)?;
+            self.cancel.reset();
+            let (report, changes) = thread::scope(|scope| {
+                let run = scope.spawn(|| self.watched_run(&selected, &previous, &light));
+                let changes = watcher.changes()?;
+                let cancelled = !run.is_finished();
+                if cancelled {
+                    println!("files changed, restarting");
+                    self.cancel.cancel();
+                }
+                let report = run.join().expect("cannot join run thread");
+                if cancelled {
+                    light.show(State::Restarting)?;
+                }
+                Ok::<_, anyhow::Error>((report, changes))
+            })?;
+            match report {
+                Some(report) => {
+                    previous = report;
+                    changed = changes;
+                }
+                None => {
+                    changed = changed
+                        .zip(changes)
+                        .map(|(changed, changes)| [changed, changes].concat())
+                }
+            }
+        }
+    }
+
+    /// Runs all tasks periodically. The light holds the last result between runs and shows the
+    /// stale color when the next run is due before the current one finishes.
+    pub(crate) fn schedule(&self, schedule: &Schedule, ack_socket: Option<&Path>) -> Result<()> {
+        let light = Light::open(&self.config, State::Off)?;
+        self.track_staleness(&light)?;
+        accept_acks(&light, ack_socket)?;
+        let mut last: Option<DateTime<Local>> = None;
+        loop {
+            if let Some(due) = schedule.next(last)? {
+                debug!("next run at {}", due);
+                wait_until(due);
+            }
+            let started = Local::now();
+            let due = schedule.next(Some(started))?;
+            self.set_fresh(false);
+            light.show(State::Pending)?;
+            let outcome = thread::scope(|scope| {
+                let run = scope.spawn(|| self.run_tasks());
+                let mut stale = false;
+                while !run.is_finished() {
+                    if !stale && due.is_some_and(|due| Local::now() >= due) {
+                        println!("scheduled run is overdue");
+                        light.show(State::Stale)?;
+                        stale = true;
+                    }
+                    thread::sleep(POLL);
+                }
+                let outcome = match run.join().expect("cannot join run thread") {
+                    Ok(report) => {
+                        println!("{report}");
+                        report.outcome()
+                    }
+                    Err(e) => {
+                        eprintln!("Error: {e:?}");
+                        Outcome::Failure
+                    }
+                };
+                Ok::<_, anyhow::Error>(outcome)
+            })?;
+            light.show(State::Done(outcome))?;
+            self.set_fresh(true);
+            last = Some(started);
+        }
+    }
+
+    /// Fades the shown result into the stale color when files change after the run, until the
+    /// next run starts. Enabled with `stale = true` in `[watch]` section, the run goes on without
+    /// it when the files cannot be watched.
+    pub(crate) fn track_staleness(&self, light: &Light) -> Result<()> {
+        if !self.config.watch().stale() {
+            return Ok(());
+        }
+        let root = self.vars.workspace_dir().canonicalize()?;
+        let watcher = match Watcher::new(&root, self.config.watch(), &Source::Files) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("Error: cannot track changes: {e:?}");
+                return Ok(());
+            }
+        };
+        let fresh = Arc::clone(&self.fresh);
+        let notifier = light.notifier();
+        thread::spawn(move || {
+            if let Err(e) = mark_stale_on_changes(&watcher, &fresh, &notifier) {
+                eprintln!("Error: cannot track changes: {e:?}");
+            }
+        });
+        Ok(())
+    }
+
+    /// Whether the shown result reflects the current files. Cleared when a run starts.
+    pub(crate) fn set_fresh(&self, fresh: bool) {
+        self.fresh.store(fresh, Ordering::SeqCst);
+    }
+
+    /// Runs selected tasks, returns combined report unless the run was cancelled or failed.
+    fn watched_run(&self, selected: &[&Task], previous: &Report, light: &Light) -> Option<Report> {
+        let (report, outcome) = match self.rerun_tasks(selected, previous) {
+            Ok(_) if self.cancel.is_cancelled() => return None,
+            Ok(report) => {
+                println!("{report}");
+                let outcome = report.outcome();
+                (Some(report), outcome)
+            }
+            Err(e) => {
+                eprintln!("Error: {e:?}");
+                (None, Outcome::Failure)
+            }
+        };
+        if let Err(e) = light.show(State::Done(outcome)) {
+            eprintln!("Error: {e:?}");
+        }
+        self.set_fresh(true);
+        report
+    }
+
+    /// Tasks watching any of the changed files together with tasks which need them, all tasks
+    /// when changes are unknown.
+    fn watching_tasks(&self, changed: Option<&[PathBuf]>, root: &Path) -> Result<Vec<&Task>> {
+        let tasks = self.config.tasks();
+        let Some(changed) = changed else {
+            return Ok(tasks.iter().collect());
+        };
+        let paths: Vec<&Path> = changed
+            .iter()
+            .filter_map(|path| path.strip_prefix(root).ok())
+            .collect();
+        let mut selected = Vec::new();
+        for task in tasks {
+            if task.watches(&paths)? {
+                selected.push(task.name());
+            }
+        }
+        while let Some(dependent) = tasks.iter().find(|t| {
+            !selected.contains(&t.name()) && t.needs().iter().any(|n| selected.contains(n))
+        }) {
+            selected.push(dependent.name());
+        }
+        debug!("tasks watching changed files: {:?}", selected);
+        Ok(tasks
+            .iter()
+            .filter(|t| selected.contains(&t.name()))
+            .collect())
+    }
+
+    /// Runs single command, passing through its exit code.
+    pub(crate) fn exec_command(&self, cmd: &str, args: &[String]) -> Result<()> {
+        // the command is run as it is, without expanding variables
+        let args: Vec<String> = args.iter().map(|arg| vars::escape(arg)).collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let task = Task::new(&vars::escape(cmd), &args);
+        self.warn_about_long_running_options();
+        let light = Light::start(&self.config)?;
+        debug!("executing {:?}", task);
+        let exit = match task.run(&self.vars) {
+            Ok(exit) => exit,
+            Err(e) => {
+                eprintln!("Error: {e:?}");
+                light.finish(Outcome::Failure)?;
+                debug!("command cannot be started, exiting with {}", NOT_STARTED);
+                process::exit(NOT_STARTED);
+            }
+        };
+        let outcome = if exit.status() == Status::Success {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        };
+        light.finish(outcome)?;
+        debug!("command finished, exiting with {}", exit.code());
+        process::exit(exit.code());
+    }
+
+    /// The light of one-shot modes keeps showing the result after blinc exits, so options of
+    /// `[light]` section which need the light to be driven between runs have no effect.
+    fn warn_about_long_running_options(&self) {
+        let ignored = self.config.light().long_running_only();
+        if !ignored.is_empty() {
+            eprintln!(
+                "Warning: `{}` options of [light] section only apply in watch, daemon and schedule modes",
+                ignored.join("`, `")
+            );
+        }
+    }
+
+    /// Runs all tasks together with hooks.
+    pub(crate) fn run_tasks(&self) -> Result<Report> {
+        let tasks: Vec<&Task> = self.config.tasks().iter().collect();
+        self.rerun_tasks(&tasks, &Report::default())
+    }
+
+    /// Runs selected tasks together with hooks, the report includes previous results of the other
+    /// tasks. `on_success` (or `on_failure`) and `finally` hooks run even when running the tasks
+    /// results with an error.
+    pub(crate) fn rerun_tasks(&self, selected: &[&Task], previous: &Report) -> Result<Report> {
+        let result = self.run_hooked_tasks(selected, previous);
+        let env = match &result {
+            Ok(report) => result_env(report.outcome(), report.failed_task()),
+            Err(_) => result_env(Outcome::Failure, None),
+        };
+        let outcome = result.as_ref().map_or(Outcome::Failure, Report::outcome);
+        if self.cancel.is_cancelled() {
+            debug!("tasks cancelled, skipping result hooks");
+        } else if outcome == Outcome::Failure {
+            self.run_hook(Hook::OnFailure, &env);
+        } else {
+            self.run_hook(Hook::OnSuccess, &env);
+        }
+        self.run_hook(Hook::Finally, &env);
+        result
+    }
+
+    fn run_hooked_tasks(&self, selected: &[&Task], previous: &Report) -> Result<Report> {
+        let mut report = Report::default();
         for task in self.config.tasks() {
-            debug!("executing {:?}", task);
-            if !task.run()?.success() {
-                tx.notify_failure()?;
-                debug!("task failed, exiting");
-                process::exit(1);
+            if selected.iter().all(|s| !ptr::eq(*s, task)) {
+                let name = task.name();
+                previous
+                    .tasks()
+                    .iter()
+                    .filter(|t| t.name() == name)
+                    .for_each(|t| report.add(t.clone()));
+            }
+        }
+        if let Some(before_all) = self.config.hook(Hook::BeforeAll) {
+            debug!("running {} hook", Hook::BeforeAll);
+            let hook_report = exec(before_all, |t| t.run(&self.vars));
+            let failed = hook_report.is_failure();
+            report.add(hook_report);
+            if failed {
+                debug!("{} hook failed, skipping tasks", Hook::BeforeAll);
+                for task in selected {
+                    report.add(TaskReport::skipped(task));
+                }
+                return Ok(report);
             }
         }
-        tx.notify_success()?;
+        self.run_pipeline(selected, &mut report)?;
+        if !self.cancel.is_cancelled() {
+            let env = result_env(report.outcome(), report.failed_task());
+            self.run_hook(Hook::AfterAll, &env);
+        }
+        Ok(report)
+    }
+
+    /// Runs the hook, its failure is reported but doesn't change the result of the tasks.
+    fn run_hook(&self, hook: Hook, env: &[(&str, String)]) {
+        let Some(task) = self.config.hook(hook) else {
+            return;
+        };
+        debug!("running {} hook", hook);
+        match task.run_with_env(&self.vars, env) {
+            Ok(exit) if exit.status() == Status::Success => {}
+            Ok(exit) => eprintln!("{hook} hook failed with exit code {}", exit.code()),
+            Err(e) => eprintln!("{hook} hook failed: {e:?}"),
+        }
+    }
+
+    fn run_pipeline(&self, selected: &[&Task], report: &mut Report) -> Result<()> {
+        let mut pending: Vec<&Task> = selected.to_vec();
+        let mut stopped = false;
+        loop {
+            stopped |= self.cancel.is_cancelled();
+            while let Some(idx) = pending.iter().position(|t| stopped || blocked(t, report)) {
+                let task = pending.remove(idx);
+                debug!("skipping {:?}", task);
+                report.add(TaskReport::skipped(task));
+            }
+            if pending.is_empty() {
+                break;
+            }
+            let stage = next_stage(&mut pending, report);
+            if stage.is_empty() {
+                bail!("cannot resolve dependencies of {:?}", pending);
+            }
+            let reports = exec_stage(&stage, &self.vars, &self.cancel);
+            if reports.iter().any(TaskReport::is_failure) && self.config.fail_fast() {
+                debug!("task failed, skipping remaining tasks");
+                stopped = true;
+            }
+            reports.into_iter().for_each(|t| report.add(t));
+        }
         Ok(())
     }
 }
 
-fn transition(config: &Config) -> Result<Transition> {
-    Ok(Transition::new(config.pending())?
-        .on_success(config.success())
-        .on_failure(config.failure()))
+/// Shows the stale color on the first change of files after the shown result was fresh.
+fn mark_stale_on_changes(watcher: &Watcher, fresh: &AtomicBool, notifier: &Notifier) -> Result<()> {
+    loop {
+        watcher.changes()?;
+        if fresh.swap(false, Ordering::SeqCst) {
+            println!("files changed since the last run");
+            notifier.show(State::Stale)?;
+        }
+    }
+}
+
+/// Acknowledges the result when Enter is pressed in the terminal, or when `cargo blinc ack` is sent
+/// to the socket.
+fn accept_acks(light: &Light, socket: Option<&Path>) -> Result<()> {
+    acknowledge_on_enter(light.notifier());
+    if let Some(socket) = socket {
+        let notifier = light.notifier();
+        daemon::serve_acks(socket, move || notifier.show(State::Acknowledged))?;
+        println!("accepting acknowledgements on {}", socket.display());
+    }
+    Ok(())
+}
+
+/// Acknowledges the result whenever Enter is pressed in the terminal.
+fn acknowledge_on_enter(notifier: Notifier) {
+    if !io::stdin().is_terminal() {
+        return;
+    }
+    thread::spawn(move || {
+        for _ in io::stdin().lock().lines().map_while(Result::ok) {
+            if notifier.show(State::Acknowledged).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Sleeps until the time, checking the clock regularly so suspending the machine doesn't delay
+/// the run.
+fn wait_until(time: DateTime<Local>) {
+    while let Ok(remaining) = (time - Local::now()).to_std() {
+        thread::sleep(remaining.min(CLOCK_CHECK));
+    }
+}
+
+/// Environment variables exposing the result of the tasks to hooks.
+fn result_env(outcome: Outcome, failed_task: Option<&str>) -> [(&'static str, String); 2] {
+    [
+        ("BLINC_RESULT", outcome.to_string()),
+        (
+            "BLINC_FAILED_TASK",
+            failed_task.unwrap_or_default().to_string(),
+        ),
+    ]
+}
+
+/// Task is blocked when any of the tasks it needs finished without success.
+fn blocked(task: &Task, report: &Report) -> bool {
+    task.needs()
+        .iter()
+        .any(|need| report.status(need).is_some_and(|s| s != Status::Success))
+}
+
+fn ready(task: &Task, report: &Report) -> bool {
+    task.needs()
+        .iter()
+        .all(|need| report.status(need) == Some(Status::Success))
+}
+
+/// Takes the first task which is ready to run, together with ready tasks from the same group.
+fn next_stage<'a>(pending: &mut Vec<&'a Task>, report: &Report) -> Vec<&'a Task> {
+    let Some(first) = pending.iter().position(|t| ready(t, report)) else {
+        return Vec::new();
+    };
+    let group = pending[first].group();
+    let (stage, rest) = pending
+        .drain(..)
+        .enumerate()
+        .partition::<Vec<_>, _>(|(idx, t)| {
+            *idx == first || (group.is_some() && t.group() == group && ready(t, report))
+        });
+    *pending = rest.into_iter().map(|(_, t)| t).collect();
+    stage.into_iter().map(|(_, t)| t).collect()
+}
+
+fn exec_stage(stage: &[&Task], vars: &Vars, cancel: &Cancel) -> Vec<TaskReport> {
+    if let [task] = stage {
+        return vec![exec(task, |t| t.run_cancellable(vars, cancel))];
+    }
+    debug!("executing {} tasks in parallel", stage.len());
+    thread::scope(|scope| {
+        let handles: Vec<_> = stage
+            .iter()
+            .map(|task| scope.spawn(move || exec(task, |t| t.run_prefixed(vars, cancel))))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("cannot join task thread"))
+            .collect()
+    })
+}
+
+/// Runs the task, a task which cannot be started is reported as failed.
+fn exec<F>(task: &Task, run: F) -> TaskReport
+where
+    F: Fn(&Task) -> Result<Exit>,
+{
+    debug!("executing {:?}", task);
+    let start = Instant::now();
+    match run(task) {
+        Ok(exit) => TaskReport::new(task, exit.status(), exit.code(), start.elapsed()),
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            TaskReport::new(task, Status::Failure, NOT_STARTED, start.elapsed())
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::Blinc;
     use crate::config::Config;
+    use crate::report::{Outcome, Status};
     use crate::testutils::{init_logger, ReaderStub};
     use std::env;
-    use std::fs::remove_file;
-    use std::path::Path;
-    use std::time::SystemTime;
+    use std::fs::{read_to_string, remove_file};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant, SystemTime};
 
     #[test]
     fn test_env_variables_are_set() {
@@ -75,10 +570,33 @@ mod test {
         "#
         .to_string();
         let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
-        Blinc::new(config);
+        Blinc::new(config).unwrap();
         assert_eq!(env::var("API_KEY").unwrap(), "10");
     }
 
+    #[test]
+    fn test_inline_env_overrides_env_from_command() {
+        let config_content = r#"
+            env_from_command = "echo '{\"BLINC_FROM_COMMAND\": \"command\", \"BLINC_OVERRIDDEN\": \"command\"}'"
+
+            [[task]]
+            cmd = "echo"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+
+            [env]
+            BLINC_OVERRIDDEN = "inline"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        Blinc::new(config).unwrap();
+        assert_eq!(env::var("BLINC_FROM_COMMAND").unwrap(), "command");
+        assert_eq!(env::var("BLINC_OVERRIDDEN").unwrap(), "inline");
+    }
+
     #[test]
     fn test_tasks_are_executed() {
         init_logger();
@@ -100,9 +618,333 @@ mod test {
         "#
         );
         let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
-        let blinc = Blinc::new(config);
+        let blinc = Blinc::new(config).unwrap();
         blinc.exec_tasks().unwrap();
         assert!(Path::new(&format!("/tmp/cargo-blinc-test-{timestamp}")).exists());
         remove_file(format!("/tmp/cargo-blinc-test-{timestamp}")).unwrap();
     }
+
+    #[test]
+    fn test_fail_fast_skips_remaining_tasks() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            cmd = "false"
+
+            [[task]]
+            cmd = "true"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        let statuses: Vec<Status> = report.tasks().iter().map(|t| t.status()).collect();
+        assert_eq!(statuses, vec![Status::Failure, Status::Skipped]);
+        assert_eq!(report.outcome(), Outcome::Failure);
+    }
+
+    #[test]
+    fn test_all_tasks_are_run_without_fail_fast() {
+        init_logger();
+        let config_content = r#"
+            fail_fast = false
+
+            [[task]]
+            cmd = "false"
+
+            [[task]]
+            cmd = "true"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        let statuses: Vec<Status> = report.tasks().iter().map(|t| t.status()).collect();
+        assert_eq!(statuses, vec![Status::Failure, Status::Success]);
+        assert_eq!(report.outcome(), Outcome::Failure);
+    }
+
+    #[test]
+    fn test_task_which_cannot_start_fails_without_stopping_run() {
+        init_logger();
+        let config_content = r#"
+            fail_fast = false
+
+            [[task]]
+            cmd = "blinc-missing-command"
+
+            [[task]]
+            cmd = "true"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        let statuses: Vec<Status> = report.tasks().iter().map(|t| t.status()).collect();
+        assert_eq!(statuses, vec![Status::Failure, Status::Success]);
+        assert_eq!(report.code(), 127);
+    }
+
+    #[test]
+    fn test_failure_of_task_exiting_with_zero_is_not_reported_with_zero_code() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            cmd = "sh"
+            args = ["-c", "echo 'error: broken'"]
+            fail_if_output_matches = "^error:"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        assert_eq!(report.outcome(), Outcome::Failure);
+        assert_eq!(report.code(), 1);
+    }
+
+    #[test]
+    fn test_allowed_failure_results_in_partial_outcome() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            cmd = "false"
+            allow_failure = true
+
+            [[task]]
+            cmd = "true"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+            partial = "yellow"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        let statuses: Vec<Status> = report.tasks().iter().map(|t| t.status()).collect();
+        assert_eq!(statuses, vec![Status::Failure, Status::Success]);
+        assert_eq!(report.outcome(), Outcome::Partial);
+    }
+
+    #[test]
+    fn test_grouped_tasks_are_run_together() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            name = "first"
+            cmd = "sleep"
+            args = ["0.5"]
+            group = "checks"
+
+            [[task]]
+            cmd = "false"
+
+            [[task]]
+            name = "second"
+            cmd = "sleep"
+            args = ["0.5"]
+            group = "checks"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let start = Instant::now();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(900));
+        let statuses: Vec<Status> = report.tasks().iter().map(|t| t.status()).collect();
+        assert_eq!(
+            statuses,
+            vec![Status::Success, Status::Success, Status::Failure]
+        );
+    }
+
+    #[test]
+    fn test_failure_in_one_branch_lets_other_branches_finish() {
+        init_logger();
+        let config_content = r#"
+            fail_fast = false
+
+            [[task]]
+            name = "test"
+            cmd = "true"
+            needs = ["check"]
+
+            [[task]]
+            name = "check"
+            cmd = "false"
+
+            [[task]]
+            name = "doc"
+            cmd = "true"
+            needs = ["test"]
+
+            [[task]]
+            name = "fmt"
+            cmd = "true"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        assert_eq!(report.status("check"), Some(Status::Failure));
+        assert_eq!(report.status("test"), Some(Status::Skipped));
+        assert_eq!(report.status("doc"), Some(Status::Skipped));
+        assert_eq!(report.status("fmt"), Some(Status::Success));
+    }
+
+    #[test]
+    fn test_tasks_run_after_their_dependencies() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            name = "second"
+            cmd = "true"
+            needs = ["first"]
+
+            [[task]]
+            name = "first"
+            cmd = "true"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        let names: Vec<&str> = report.tasks().iter().map(|t| t.name()).collect();
+        assert_eq!(names, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_hooks_run_around_tasks() {
+        init_logger();
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let log = format!("/tmp/cargo-blinc-test-hooks-{timestamp}");
+        let config_content = format!(
+            r#"
+            [[task]]
+            name = "check"
+            cmd = "sh"
+            args = ["-c", "echo check >> {log}; exit 1"]
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+
+            [hooks]
+            before_all = {{ cmd = "sh", args = ["-c", "echo before_all >> {log}"] }}
+            after_all = {{ cmd = "sh", args = ["-c", "echo after_all $BLINC_RESULT >> {log}"] }}
+            on_success = {{ cmd = "sh", args = ["-c", "echo on_success >> {log}"] }}
+            on_failure = {{ cmd = "sh", args = ["-c", "echo on_failure $BLINC_FAILED_TASK >> {log}"] }}
+            finally = {{ cmd = "sh", args = ["-c", "echo finally >> {log}"] }}
+        "#
+        );
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        assert_eq!(report.outcome(), Outcome::Failure);
+        assert_eq!(
+            read_to_string(&log).unwrap(),
+            "before_all\ncheck\nafter_all failure\non_failure check\nfinally\n"
+        );
+        remove_file(&log).unwrap();
+    }
+
+    #[test]
+    fn test_tasks_are_skipped_when_before_all_hook_fails() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            name = "check"
+            cmd = "true"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+
+            [hooks]
+            before_all = { name = "setup", cmd = "false" }
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let report = Blinc::new(config).unwrap().run_tasks().unwrap();
+        assert_eq!(report.status("setup"), Some(Status::Failure));
+        assert_eq!(report.status("check"), Some(Status::Skipped));
+        assert_eq!(report.failed_task(), Some("setup"));
+    }
+
+    #[test]
+    fn test_only_tasks_watching_changed_files_run_again() {
+        init_logger();
+        let config_content = r#"
+            fail_fast = false
+
+            [[task]]
+            name = "docs"
+            cmd = "true"
+            watch = ["**/*.md"]
+
+            [[task]]
+            name = "test"
+            cmd = "false"
+            watch = ["src/**/*.rs"]
+
+            [[task]]
+            name = "integration"
+            cmd = "true"
+            needs = ["docs"]
+            watch = ["tests/**"]
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let config = Config::read(&mut ReaderStub::new(config_content)).unwrap();
+        let blinc = Blinc::new(config).unwrap();
+        let previous = blinc.run_tasks().unwrap();
+        let changed = vec![PathBuf::from("/workspace/docs/guide.md")];
+        let selected = blinc
+            .watching_tasks(Some(&changed), Path::new("/workspace"))
+            .unwrap();
+        let names: Vec<String> = selected.iter().map(|t| t.name()).collect();
+        assert_eq!(names, vec!["docs", "integration"]);
+        let report = blinc.rerun_tasks(&selected, &previous).unwrap();
+        assert_eq!(report.status("docs"), Some(Status::Success));
+        assert_eq!(report.status("test"), Some(Status::Failure));
+        assert_eq!(report.status("integration"), Some(Status::Success));
+        assert_eq!(report.outcome(), Outcome::Failure);
+    }
 }
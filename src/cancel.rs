@@ -0,0 +1,109 @@
+use anyhow::Result;
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Token allowing to cancel running tasks from another thread.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub(crate) fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Kills the process together with all its descendants. Processes are stopped first, so they
+/// can't spawn new processes while the tree is collected.
+pub(crate) fn kill_tree(pid: u32) -> Result<()> {
+    let mut stopped: Vec<u32> = Vec::new();
+    loop {
+        let tree: Vec<u32> = Some(pid)
+            .into_iter()
+            .chain(descendants(pid)?)
+            .filter(|p| !stopped.contains(p))
+            .collect();
+        if tree.is_empty() {
+            break;
+        }
+        for p in tree {
+            signal(p, libc::SIGSTOP);
+            stopped.push(p);
+        }
+    }
+    debug!("killing processes {:?}", stopped);
+    for p in stopped {
+        signal(p, libc::SIGKILL);
+    }
+    Ok(())
+}
+
+fn signal(pid: u32, signal: libc::c_int) {
+    // the process might have already exited, which is fine
+    unsafe { libc::kill(pid as libc::pid_t, signal) };
+}
+
+fn descendants(pid: u32) -> Result<Vec<u32>> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for entry in fs::read_dir("/proc")? {
+        let Some(child) = entry?.file_name().to_str().and_then(|n| n.parse().ok()) else {
+            continue;
+        };
+        if let Some(parent) = parent(child) {
+            children.entry(parent).or_default().push(child);
+        }
+    }
+    let mut descendants = Vec::new();
+    let mut queue = vec![pid];
+    while let Some(p) = queue.pop() {
+        for &child in children.get(&p).into_iter().flatten() {
+            descendants.push(child);
+            queue.push(child);
+        }
+    }
+    Ok(descendants)
+}
+
+/// Reads parent pid from `/proc/<pid>/stat`, the field after the command in parentheses.
+fn parent(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::kill_tree;
+    use crate::Result;
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_process_is_killed_with_descendants() -> Result<()> {
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 30 & echo $! > /tmp/blinc-kill-tree; wait"])
+            .spawn()?;
+        thread::sleep(Duration::from_millis(200));
+        let grandchild: u32 = std::fs::read_to_string("/tmp/blinc-kill-tree")?
+            .trim()
+            .parse()?;
+        kill_tree(child.id())?;
+        child.wait()?;
+        thread::sleep(Duration::from_millis(100));
+        let stat = std::fs::read_to_string(format!("/proc/{grandchild}/stat")).unwrap_or_default();
+        // killed process is gone or waits as a zombie for its parent
+        assert!(stat.is_empty() || stat.contains(") Z "));
+        Ok(())
+    }
+}
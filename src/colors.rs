@@ -7,6 +7,8 @@ pub(crate) struct Colors {
     pending: Vec<Led>,
     failure: Led,
     success: Led,
+    partial: Option<Led>,
+    stale: Option<Led>,
 }
 
 impl Colors {
@@ -15,6 +17,8 @@ impl Colors {
             pending,
             failure,
             success,
+            partial: None,
+            stale: None,
         }
     }
 
@@ -29,4 +33,13 @@ impl Colors {
     pub(crate) fn success(&self) -> &Led {
         &self.success
     }
+
+    pub(crate) fn partial(&self) -> &Led {
+        self.partial.as_ref().unwrap_or(&Led::Orange)
+    }
+
+    /// Color of the result which no longer reflects the current state.
+    pub(crate) fn stale(&self) -> &Led {
+        self.stale.as_ref().unwrap_or(&Led::Yellow)
+    }
 }
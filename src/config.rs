@@ -1,23 +1,42 @@
 use crate::colors::Colors;
-use crate::task::Task;
-use anyhow::Result;
+use crate::environment::EnvValue;
+use crate::error::BlincErr;
+use crate::hooks::{Hook, Hooks};
+use crate::light::LightSettings;
+use crate::schedule::Schedule;
+use crate::task::{Stdin, StdinMode, Task};
+use crate::vars::Vars;
+use crate::watch::Watch;
+use anyhow::{bail, Result};
 use log::debug;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use transition::Led;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct Config {
+    fail_fast: Option<bool>,
+    env_files: Option<Vec<String>>,
+    env_from_command: Option<String>,
     #[serde(rename = "task")]
     tasks: Vec<Task>,
     colors: Colors,
-    env: Option<HashMap<String, String>>,
+    light: Option<LightSettings>,
+    hooks: Option<Hooks>,
+    watch: Option<Watch>,
+    schedule: Option<Schedule>,
+    env: Option<HashMap<String, EnvValue>>,
+    vars: Option<HashMap<String, String>>,
+    #[serde(skip)]
+    dir: Option<PathBuf>,
+    #[serde(skip)]
+    profile: Option<String>,
 }
 
 impl Config {
@@ -25,7 +44,9 @@ impl Config {
         let path = path.as_ref();
         if Path::new(path).exists() {
             debug!("config file exists, loading from path {:?}", path);
-            Ok(Self::load(path)?)
+            let mut config = Self::load(path).map_err(BlincErr::Config)?;
+            config.dir = path.parent().map(Path::to_path_buf);
+            Ok(config)
         } else {
             debug!("no config file, using default configuration");
             Ok(Self::default())
@@ -39,12 +60,67 @@ impl Config {
     pub(crate) fn read<R: Read>(read: &mut R) -> Result<Self> {
         let mut config_content = String::new();
         read.read_to_string(&mut config_content)?;
-        debug!("read config {}", config_content);
-        let config = toml::from_str(&config_content)?;
+        debug!("read config of {} bytes", config_content.len());
+        let config: Self = toml::from_str(&config_content)?;
         debug!("created config struct: {:?}", config);
+        config.validate()?;
         Ok(config)
     }
 
+    fn validate(&self) -> Result<()> {
+        for value in self.env.iter().flat_map(HashMap::values) {
+            value.validate()?;
+        }
+        for hook in self.hooks.iter().flat_map(Hooks::all) {
+            hook.validate()?;
+        }
+        if let Some(light) = &self.light {
+            light.validate()?;
+        }
+        if let Some(watch) = &self.watch {
+            watch.validate()?;
+        }
+        if let Some(schedule) = &self.schedule {
+            schedule.validate()?;
+        }
+        // tasks are identified by their names in reports, selection and dependencies
+        let mut names = HashSet::new();
+        for task in &self.tasks {
+            if !names.insert(task.name()) {
+                bail!(
+                    "duplicate task `{}`, tasks need distinct names",
+                    task.name()
+                );
+            }
+        }
+        for hook in self.hooks.iter().flat_map(Hooks::all) {
+            if names.contains(&hook.name()) {
+                bail!("task `{}` has the same name as a hook", hook.name());
+            }
+        }
+        for task in &self.tasks {
+            task.validate()?;
+            for need in task.needs() {
+                if !names.contains(need) {
+                    bail!("task `{}` needs unknown task `{}`", task.name(), need);
+                }
+            }
+        }
+        if let Some(cycle) = self.find_cycle() {
+            bail!("tasks dependency cycle: {}", cycle.join(" -> "));
+        }
+        Ok(())
+    }
+
+    fn find_cycle(&self) -> Option<Vec<String>> {
+        let needs: HashMap<String, &[String]> =
+            self.tasks.iter().map(|t| (t.name(), t.needs())).collect();
+        let mut visited = HashSet::new();
+        self.tasks
+            .iter()
+            .find_map(|t| visit(&t.name(), &needs, &mut visited, &mut Vec::new()))
+    }
+
     pub(crate) fn store<A: AsRef<Path>>(&self, path: A) -> Result<()> {
         debug!(
             "storing config: {:?} under path {:?} with name {:?}",
@@ -67,14 +143,142 @@ impl Config {
         Ok(())
     }
 
+    /// Tasks with specified names or tags, together with tasks they need. All tasks when neither
+    /// names nor tags are specified.
+    pub(crate) fn selected(&self, names: &[String], tags: &[String]) -> Result<Vec<&Task>> {
+        if names.is_empty() && tags.is_empty() {
+            return Ok(self.tasks.iter().collect());
+        }
+        if let Some(name) = names
+            .iter()
+            .find(|n| !self.tasks.iter().any(|t| &t.name() == *n))
+        {
+            bail!("unknown task `{}`", name);
+        }
+        if let Some(tag) = tags
+            .iter()
+            .find(|tag| !self.tasks.iter().any(|t| t.tags().contains(tag)))
+        {
+            bail!("no task tagged `{}`", tag);
+        }
+        let mut selected: Vec<String> = self
+            .tasks
+            .iter()
+            .filter(|t| names.contains(&t.name()) || t.tags().iter().any(|tag| tags.contains(tag)))
+            .map(Task::name)
+            .collect();
+        let mut idx = 0;
+        while let Some(name) = selected.get(idx).cloned() {
+            let needs = self
+                .tasks
+                .iter()
+                .filter(|t| t.name() == name)
+                .flat_map(Task::needs);
+            for need in needs {
+                if !selected.contains(need) {
+                    selected.push(need.clone());
+                }
+            }
+            idx += 1;
+        }
+        Ok(self
+            .tasks
+            .iter()
+            .filter(|t| selected.contains(&t.name()))
+            .collect())
+    }
+
+    /// Keeps only tasks with specified names or tags, together with tasks they need. Keeps all tasks
+    /// when neither names nor tags are specified.
+    pub(crate) fn select(&mut self, names: &[String], tags: &[String]) -> Result<()> {
+        let selected: Vec<String> = self
+            .selected(names, tags)?
+            .into_iter()
+            .map(Task::name)
+            .collect();
+        debug!("selected tasks: {:?}", selected);
+        self.tasks.retain(|t| selected.contains(&t.name()));
+        Ok(())
+    }
+
+    pub(crate) fn pass_args(&mut self, args: &[String]) {
+        debug!("passing {:?} to tasks", args);
+        self.tasks.iter_mut().for_each(|t| t.pass_args(args));
+    }
+
+    /// Makes tasks and hooks without `stdin` option read from the null device, so they can't wait
+    /// for input in non-interactive runs.
+    pub(crate) fn disable_stdin(&mut self) {
+        debug!("disabling stdin of tasks");
+        let stdin = Stdin::Mode(StdinMode::Null);
+        self.tasks
+            .iter_mut()
+            .chain(self.hooks.iter_mut().flat_map(Hooks::all_mut))
+            .for_each(|t| t.default_stdin(stdin.clone()));
+    }
+
     pub(crate) fn tasks(&self) -> &Vec<Task> {
         &self.tasks
     }
 
-    pub(crate) fn env(&self) -> &Option<HashMap<String, String>> {
+    /// Stop executing tasks after the first failure. Enabled by default.
+    pub(crate) fn fail_fast(&self) -> bool {
+        self.fail_fast.unwrap_or(true)
+    }
+
+    pub(crate) fn light(&self) -> &LightSettings {
+        static DEFAULT: LightSettings = LightSettings::DEFAULT;
+        self.light.as_ref().unwrap_or(&DEFAULT)
+    }
+
+    pub(crate) fn watch(&self) -> &Watch {
+        static DEFAULT: Watch = Watch::DEFAULT;
+        self.watch.as_ref().unwrap_or(&DEFAULT)
+    }
+
+    pub(crate) fn schedule(&self) -> Option<&Schedule> {
+        self.schedule.as_ref()
+    }
+
+    pub(crate) fn hook(&self, hook: Hook) -> Option<&Task> {
+        self.hooks.as_ref().and_then(|hooks| hooks.get(hook))
+    }
+
+    /// Paths of `.env` files, relative to the configuration file directory.
+    pub(crate) fn env_files(&self) -> &[String] {
+        self.env_files.as_deref().unwrap_or_default()
+    }
+
+    /// Command printing environment variables as JSON object.
+    pub(crate) fn env_from_command(&self) -> Option<&str> {
+        self.env_from_command.as_deref()
+    }
+
+    pub(crate) fn env(&self) -> &Option<HashMap<String, EnvValue>> {
         &self.env
     }
 
+    pub(crate) fn set_profile(&mut self, profile: &str) {
+        self.profile = Some(profile.to_string());
+    }
+
+    /// Directory of the configuration file, current directory for default configuration.
+    pub(crate) fn dir(&self) -> Result<PathBuf> {
+        match &self.dir {
+            Some(dir) if !dir.as_os_str().is_empty() => Ok(dir.canonicalize()?),
+            _ => Ok(env::current_dir()?),
+        }
+    }
+
+    /// Variables available for interpolation in tasks.
+    pub(crate) fn vars(&self) -> Result<Vars> {
+        Ok(Vars::new(
+            self.vars.clone().unwrap_or_default(),
+            self.dir()?,
+            self.profile.as_deref().unwrap_or("dev"),
+        ))
+    }
+
     pub(crate) fn pending(&self) -> &[Led] {
         self.colors.pending()
     }
@@ -86,17 +290,59 @@ impl Config {
     pub(crate) fn success(&self) -> &Led {
         self.colors.success()
     }
+
+    pub(crate) fn partial(&self) -> &Led {
+        self.colors.partial()
+    }
+
+    pub(crate) fn stale(&self) -> &Led {
+        self.colors.stale()
+    }
+}
+
+fn visit(
+    name: &str,
+    needs: &HashMap<String, &[String]>,
+    visited: &mut HashSet<String>,
+    path: &mut Vec<String>,
+) -> Option<Vec<String>> {
+    if let Some(idx) = path.iter().position(|n| n == name) {
+        let mut cycle = path[idx..].to_vec();
+        cycle.push(name.to_string());
+        return Some(cycle);
+    }
+    if !visited.insert(name.to_string()) {
+        return None;
+    }
+    path.push(name.to_string());
+    for need in needs.get(name).copied().unwrap_or_default() {
+        if let Some(cycle) = visit(need, needs, visited, path) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    None
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            fail_fast: None,
+            env_files: None,
+            env_from_command: None,
             tasks: vec![
                 Task::new("cargo", &["check"]),
                 Task::new("cargo", &["test"]),
             ],
             colors: Colors::new(vec![Led::Blue, Led::Blank], Led::Red, Led::Green),
+            light: None,
+            hooks: None,
+            watch: None,
+            schedule: None,
             env: Some(HashMap::new()),
+            vars: None,
+            dir: None,
+            profile: None,
         }
     }
 }
@@ -104,6 +350,7 @@ impl Default for Config {
 #[cfg(test)]
 mod test {
     use super::Config;
+    use crate::error;
     use crate::testutils::{init_logger, ReaderStub, WriterMock};
     use crate::Result;
     use transition::Led;
@@ -206,6 +453,7 @@ mod test {
             cmd = "cargo"
 
             [[task]]
+            name = "build"
             cmd = "cargo"
 
             [colors]
@@ -314,6 +562,138 @@ mod test {
         Config::read(&mut ReaderStub::new(config_content)).unwrap();
     }
 
+    #[test]
+    fn test_tasks_config_with_dependency_cycle() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            name = "check"
+            cmd = "cargo"
+            needs = ["test"]
+
+            [[task]]
+            name = "test"
+            cmd = "cargo"
+            needs = ["check"]
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let err = Config::read(&mut ReaderStub::new(config_content)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tasks dependency cycle: check -> test -> check"
+        );
+    }
+
+    #[test]
+    fn test_tasks_config_with_unknown_dependency() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            name = "test"
+            cmd = "cargo"
+            needs = ["check"]
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let err = Config::read(&mut ReaderStub::new(config_content)).unwrap_err();
+        assert_eq!(err.to_string(), "task `test` needs unknown task `check`");
+    }
+
+    #[test]
+    fn test_tasks_config_with_duplicate_names() {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            cmd = "cargo"
+            args = ["test"]
+
+            [[task]]
+            cmd = "cargo"
+            args = ["test"]
+            cwd = "member"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let err = Config::read(&mut ReaderStub::new(config_content)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "duplicate task `cargo test`, tasks need distinct names"
+        );
+        let config_content = r#"
+            [hooks]
+            before_all = { cmd = "make", args = ["setup"] }
+
+            [[task]]
+            cmd = "make"
+            args = ["setup"]
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let err = Config::read(&mut ReaderStub::new(config_content)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "task `make setup` has the same name as a hook"
+        );
+    }
+
+    #[test]
+    fn test_select_tasks_by_name_and_tag() -> Result<()> {
+        init_logger();
+        let config_content = r#"
+            [[task]]
+            name = "check"
+            cmd = "cargo"
+
+            [[task]]
+            name = "test"
+            cmd = "cargo"
+            needs = ["check"]
+
+            [[task]]
+            name = "clippy"
+            cmd = "cargo"
+            tags = ["fast"]
+
+            [[task]]
+            name = "doc"
+            cmd = "cargo"
+
+            [colors]
+            pending = ["blue", "blank"]
+            failure = "red"
+            success = "green"
+        "#
+        .to_string();
+        let mut c = Config::read(&mut ReaderStub::new(config_content))?;
+
+        c.select(&["test".to_string()], &["fast".to_string()])?;
+
+        let names: Vec<String> = c.tasks().iter().map(|t| t.name()).collect();
+        assert_eq!(names, vec!["check", "test", "clippy"]);
+        let err = c.select(&["unknown".to_string()], &[]).unwrap_err();
+        assert_eq!(error::exit_code(&err), 1);
+        let err = c.select(&[], &["unknown".to_string()]).unwrap_err();
+        assert_eq!(error::exit_code(&err), 1);
+        Ok(())
+    }
+
     #[test]
     fn test_store_config() -> Result<()> {
         init_logger();
@@ -0,0 +1,377 @@
+use crate::blinc::Blinc;
+use crate::error;
+use crate::light::{Light, State};
+use crate::report::{Outcome, Report};
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::process;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Command sent to the daemon as a single line of JSON.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum Request {
+    /// Runs specified tasks (all when empty), cancelling the run in progress.
+    Run {
+        #[serde(default)]
+        tasks: Vec<String>,
+    },
+    /// Returns the result of the last run.
+    Status,
+    /// Shows the state on the light until the next run.
+    SetState { state: Shown },
+    /// Cancels the run in progress.
+    Cancel,
+    /// Acknowledges the result of the last run, which turns the light off or clears the sticky
+    /// failure.
+    Ack,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Shown {
+    Pending,
+    Success,
+    Partial,
+    Failure,
+    Off,
+}
+
+impl From<Shown> for State {
+    fn from(shown: Shown) -> Self {
+        match shown {
+            Shown::Pending => State::Pending,
+            Shown::Success => State::Done(Outcome::Success),
+            Shown::Partial => State::Done(Outcome::Partial),
+            Shown::Failure => State::Done(Outcome::Failure),
+            Shown::Off => State::Off,
+        }
+    }
+}
+
+/// State shared between the connections and the thread running tasks.
+#[derive(Default)]
+struct Shared {
+    running: bool,
+    report: Option<Report>,
+}
+
+struct Daemon<'a> {
+    blinc: &'a Blinc,
+    light: Light,
+    runs: Sender<Vec<String>>,
+    shared: Mutex<Shared>,
+}
+
+/// Listens for commands on the Unix socket and runs the tasks on request, holding the device
+/// open between runs.
+pub(crate) fn serve(blinc: &Blinc, socket: &Path) -> Result<()> {
+    let listener = bind(socket)?;
+    println!("listening on {}", socket.display());
+    let (sender, receiver) = channel();
+    let daemon = Daemon {
+        blinc,
+        light: Light::open(blinc.config(), State::Off)?,
+        runs: sender,
+        shared: Mutex::new(Shared::default()),
+    };
+    blinc.track_staleness(&daemon.light)?;
+    thread::scope(|scope| {
+        let daemon = &daemon;
+        scope.spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        scope.spawn(move || handle(stream, |line| daemon.respond(line)));
+                    }
+                    Err(e) => eprintln!("Error: cannot accept connection: {e:?}"),
+                }
+            }
+        });
+        for tasks in receiver {
+            // the light can't recover, e.g. when the device was unplugged, so the daemon stops
+            // instead of answering requests it can't handle
+            if let Err(e) = daemon.run(&tasks) {
+                eprintln!("Error: {e:?}");
+                process::exit(error::exit_code(&e));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Accepts acknowledgements of results shown in other long-running modes on the Unix socket, so
+/// `cargo blinc ack` reaches them. Other requests need the daemon and are rejected.
+pub(crate) fn serve_acks<F>(socket: &Path, ack: F) -> Result<()>
+where
+    F: Fn() -> Result<()> + Clone + Send + 'static,
+{
+    let listener = bind(socket)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let ack = ack.clone();
+                    thread::spawn(move || handle(stream, |line| respond_to_ack(line, &ack)));
+                }
+                Err(e) => eprintln!("Error: cannot accept connection: {e:?}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn respond_to_ack<F: Fn() -> Result<()>>(line: &str, ack: &F) -> Result<Value> {
+    let request: Request = serde_json::from_str(line)?;
+    debug!("received {:?}", request);
+    match request {
+        Request::Ack => ack()?,
+        _ => bail!("only `ack` command is accepted outside of daemon mode"),
+    }
+    Ok(json!({ "ok": true }))
+}
+
+/// Answers requests received on the connection, one JSON line for each line.
+fn handle<F: Fn(&str) -> Result<Value>>(stream: UnixStream, respond: F) {
+    debug!("client connected");
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => return eprintln!("Error: cannot handle connection: {e:?}"),
+    };
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match respond(&line) {
+            Ok(response) => response,
+            Err(e) => json!({ "ok": false, "error": format!("{e:#}") }),
+        };
+        if writeln!(writer, "{response}").is_err() {
+            break;
+        }
+    }
+    debug!("client disconnected");
+}
+
+/// Binds the socket, replacing the socket file left by a daemon which is no longer running.
+pub(crate) fn bind(socket: &Path) -> Result<UnixListener> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            bail!("daemon is already listening on {}", socket.display());
+        }
+        debug!("removing stale socket {:?}", socket);
+        fs::remove_file(socket)?;
+    }
+    Ok(UnixListener::bind(socket)?)
+}
+
+/// Sends acknowledgement to the daemon. Returns `false` when no daemon listens on the socket, fails
+/// when something else listens there, e.g. `cargo blinc watch --socket` which never responds.
+pub(crate) fn acknowledge(socket: &Path) -> Result<bool> {
+    let Ok(mut stream) = UnixStream::connect(socket) else {
+        debug!("no daemon listening on {:?}", socket);
+        return Ok(false);
+    };
+    stream.set_read_timeout(Some(ACK_TIMEOUT))?;
+    writeln!(stream, "{}", json!({ "command": "ack" }))?;
+    let mut response = String::new();
+    if !matches!(BufReader::new(stream).read_line(&mut response), Ok(read) if read > 0) {
+        bail!("no daemon response on socket {:?}", socket);
+    }
+    let response: Value = serde_json::from_str(&response)
+        .map_err(|e| anyhow!("invalid daemon response on socket {:?}: {e}", socket))?;
+    if response["ok"] != json!(true) {
+        bail!("daemon rejected acknowledgement: {}", response["error"]);
+    }
+    Ok(true)
+}
+
+impl Daemon<'_> {
+    fn shared(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().expect("daemon state poisoned")
+    }
+
+    fn run(&self, tasks: &[String]) -> Result<()> {
+        let selected = self.blinc.config().selected(tasks, &[])?;
+        let previous = {
+            let mut shared = self.shared();
+            shared.running = true;
+            shared.report.clone().unwrap_or_default()
+        };
+        self.blinc.cancel().reset();
+        self.blinc.set_fresh(false);
+        self.light.show(State::Pending)?;
+        let result = self.blinc.rerun_tasks(&selected, &previous);
+        let mut shared = self.shared();
+        shared.running = false;
+        let outcome = match result {
+            Ok(_) if self.blinc.cancel().is_cancelled() => {
+                debug!("run cancelled");
+                shared.report.as_ref().map(Report::outcome)
+            }
+            Ok(report) => {
+                println!("{report}");
+                let outcome = report.outcome();
+                shared.report = Some(report);
+                Some(outcome)
+            }
+            Err(e) => {
+                eprintln!("Error: {e:?}");
+                Some(Outcome::Failure)
+            }
+        };
+        self.light.show(outcome.map_or(State::Off, State::Done))?;
+        self.blinc.set_fresh(outcome.is_some());
+        Ok(())
+    }
+
+    fn respond(&self, line: &str) -> Result<Value> {
+        let request: Request = serde_json::from_str(line)?;
+        debug!("received {:?}", request);
+        match request {
+            Request::Run { tasks } => {
+                self.blinc.config().selected(&tasks, &[])?;
+                self.blinc.cancel().cancel();
+                self.runs
+                    .send(tasks)
+                    .map_err(|_| anyhow!("daemon is shutting down"))?;
+            }
+            Request::Status => return Ok(self.status()),
+            Request::SetState { state } => self.light.show(state.into())?,
+            Request::Cancel => self.blinc.cancel().cancel(),
+            Request::Ack => self.light.show(State::Acknowledged)?,
+        }
+        Ok(json!({ "ok": true }))
+    }
+
+    fn status(&self) -> Value {
+        let shared = self.shared();
+        let Some(report) = &shared.report else {
+            return json!({ "ok": true, "running": shared.running, "outcome": null, "tasks": [] });
+        };
+        let tasks: Vec<Value> = report
+            .tasks()
+            .iter()
+            .map(|t| {
+                json!({
+                    "name": t.name(),
+                    "status": t.status().to_string(),
+                    "code": t.code(),
+                    "duration": t.duration().map(|d| d.as_secs_f64()),
+                })
+            })
+            .collect();
+        json!({
+            "ok": true,
+            "running": shared.running,
+            "outcome": report.outcome().to_string(),
+            "tasks": tasks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{acknowledge, bind, serve_acks, Request, Shown, ACK_TIMEOUT};
+    use crate::Result;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    #[test]
+    fn test_requests_are_parsed_from_json_lines() -> Result<()> {
+        let request: Request = serde_json::from_str(r#"{"command": "run", "tasks": ["test"]}"#)?;
+        assert!(matches!(request, Request::Run { tasks } if tasks == vec!["test"]));
+        let request: Request = serde_json::from_str(r#"{"command": "run"}"#)?;
+        assert!(matches!(request, Request::Run { tasks } if tasks.is_empty()));
+        let request: Request =
+            serde_json::from_str(r#"{"command": "set-state", "state": "failure"}"#)?;
+        assert!(matches!(
+            request,
+            Request::SetState {
+                state: Shown::Failure
+            }
+        ));
+        assert!(serde_json::from_str::<Request>(r#"{"command": "explode"}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_socket_is_replaced() -> Result<()> {
+        let socket = std::env::temp_dir().join("blinc-daemon-test.sock");
+        let _ = fs::remove_file(&socket);
+        drop(bind(&socket)?);
+        let _listener = bind(&socket)?;
+        assert!(bind(&socket).is_err());
+        fs::remove_file(&socket)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_acknowledgement_is_sent_to_daemon() -> Result<()> {
+        let socket = std::env::temp_dir().join("blinc-ack-test.sock");
+        let _ = fs::remove_file(&socket);
+        assert!(!acknowledge(&socket)?);
+        let listener = bind(&socket)?;
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            writeln!(&stream, r#"{{"ok": true}}"#).unwrap();
+            request
+        });
+        assert!(acknowledge(&socket)?);
+        let request: Request = serde_json::from_str(&server.join().unwrap())?;
+        assert!(matches!(request, Request::Ack));
+        fs::remove_file(&socket)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_acknowledgements_are_accepted_outside_of_daemon() -> Result<()> {
+        let socket = std::env::temp_dir().join("blinc-ack-only-test.sock");
+        let _ = fs::remove_file(&socket);
+        let (sender, receiver) = channel();
+        serve_acks(&socket, move || Ok(sender.send(())?))?;
+        assert!(acknowledge(&socket)?);
+        assert!(receiver.try_recv().is_ok());
+        let stream = UnixStream::connect(&socket)?;
+        writeln!(&stream, r#"{{"command": "run"}}"#)?;
+        let mut response = String::new();
+        BufReader::new(&stream).read_line(&mut response)?;
+        assert!(response.contains(r#""ok":false"#));
+        assert!(receiver.try_recv().is_err());
+        fs::remove_file(&socket)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_acknowledgement_fails_without_daemon_response() -> Result<()> {
+        let socket = std::env::temp_dir().join("blinc-ack-silent-test.sock");
+        let _ = fs::remove_file(&socket);
+        let listener = bind(&socket)?;
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            // keeps the connection open without responding, like a watch trigger socket
+            thread::sleep(ACK_TIMEOUT * 2);
+            drop(stream);
+        });
+        assert!(acknowledge(&socket).is_err());
+        server.join().unwrap();
+        fs::remove_file(&socket)?;
+        Ok(())
+    }
+}
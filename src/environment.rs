@@ -0,0 +1,206 @@
+use crate::vars::Vars;
+use anyhow::{bail, Context, Result};
+use log::debug;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Value of environment variable. Either plain text, or a table with `value`, `file` or `command`
+/// source. Values marked as `secret` are redacted in logs, values read from a file or a command
+/// are secret by default.
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum EnvValue {
+    Plain(String),
+    Source(EnvSource),
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EnvSource {
+    value: Option<String>,
+    file: Option<String>,
+    command: Option<String>,
+    secret: Option<bool>,
+}
+
+impl EnvValue {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let EnvValue::Source(source) = self {
+            let count = [&source.value, &source.file, &source.command]
+                .iter()
+                .filter(|s| s.is_some())
+                .count();
+            if count != 1 {
+                bail!("env value needs exactly one of `value`, `file` or `command`");
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_secret(&self) -> bool {
+        match self {
+            EnvValue::Plain(_) => false,
+            EnvValue::Source(source) => source.secret.unwrap_or(source.value.is_none()),
+        }
+    }
+
+    /// Resolves the value, expanding variables. Relative file paths start in the configuration
+    /// file directory.
+    pub(crate) fn resolve(&self, vars: &Vars) -> Result<String> {
+        let source = match self {
+            EnvValue::Plain(value) => return vars.expand(value),
+            EnvValue::Source(source) => source,
+        };
+        if let Some(value) = &source.value {
+            return vars.expand(value);
+        }
+        if let Some(file) = &source.file {
+            let path = vars.config_dir().join(vars.expand(file)?);
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("cannot read env value from {path:?}"))?;
+            return Ok(content.trim_end_matches('\n').to_string());
+        }
+        if let Some(command) = &source.command {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(vars.expand(command)?)
+                .output()?;
+            if !output.status.success() {
+                bail!("env command `{}` failed with {}", command, output.status);
+            }
+            let value = String::from_utf8(output.stdout)?;
+            return Ok(value.trim_end_matches('\n').to_string());
+        }
+        bail!("env value needs exactly one of `value`, `file` or `command`")
+    }
+}
+
+impl fmt::Debug for EnvValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            _ if self.is_secret() => write!(f, "\"***\""),
+            EnvValue::Plain(value) => write!(f, "{value:?}"),
+            EnvValue::Source(source) => write!(f, "{source:?}"),
+        }
+    }
+}
+
+impl fmt::Debug for EnvSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvSource")
+            .field("value", &self.value)
+            .field("file", &self.file)
+            .field("command", &self.command)
+            .finish()
+    }
+}
+
+/// Reads variables from the `.env` file. Missing file is treated as empty.
+pub(crate) fn from_file<A: AsRef<Path>>(path: A) -> Result<Vec<(String, String)>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        debug!("env file {:?} does not exist, skipping", path);
+        return Ok(Vec::new());
+    }
+    debug!("reading env file {:?}", path);
+    let vars = dotenvy::from_path_iter(path)?
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("cannot parse env file {path:?}"))?;
+    Ok(vars)
+}
+
+/// Runs the command printing variables as JSON object, e.g. `direnv export json`. Variables with
+/// `null` value are meant to be unset.
+pub(crate) fn from_command(command: &str) -> Result<HashMap<String, Option<String>>> {
+    debug!("reading env from command {:?}", command);
+    let output = Command::new("sh").arg("-c").arg(command).output()?;
+    if !output.status.success() {
+        bail!("env command `{}` failed with {}", command, output.status);
+    }
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(HashMap::new());
+    }
+    let vars = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("env command `{command}` did not print JSON object"))?;
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_command, from_file, EnvValue};
+    use crate::vars::Vars;
+    use crate::Result;
+    use std::collections::HashMap;
+    use std::fs::{remove_file, write};
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_variables_are_read_from_file() -> Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis();
+        let path = format!("/tmp/cargo-blinc-test-{timestamp}.env");
+        write(&path, "# comment\nAPI_KEY=secret\nexport NAME=\"blinc\"\n")?;
+        let vars = from_file(&path)?;
+        remove_file(&path)?;
+        assert_eq!(
+            vars,
+            vec![
+                ("API_KEY".to_string(), "secret".to_string()),
+                ("NAME".to_string(), "blinc".to_string())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_file_is_skipped() -> Result<()> {
+        assert!(from_file("/tmp/cargo-blinc-missing.env")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_variables_are_read_from_command() -> Result<()> {
+        let vars = from_command(r#"echo '{"API_KEY": "secret", "OLD": null}'"#)?;
+        assert_eq!(vars["API_KEY"], Some("secret".to_string()));
+        assert_eq!(vars["OLD"], None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failing_command_is_an_error() {
+        assert!(from_command("exit 1").is_err());
+    }
+
+    fn env(config: &str) -> HashMap<String, EnvValue> {
+        toml::from_str(config).unwrap()
+    }
+
+    #[test]
+    fn test_secret_values_are_redacted() -> Result<()> {
+        let env = env(r#"
+            PLAIN = "visible"
+            MARKED = { value = "hidden", secret = true }
+            FROM_COMMAND = { command = "echo hidden" }
+            "#);
+        assert_eq!(format!("{:?}", env["PLAIN"]), "\"visible\"");
+        assert_eq!(format!("{:?}", env["MARKED"]), "\"***\"");
+        assert_eq!(format!("{:?}", env["FROM_COMMAND"]), "\"***\"");
+        assert_eq!(env["MARKED"].resolve(&Vars::default())?, "hidden");
+        assert_eq!(env["FROM_COMMAND"].resolve(&Vars::default())?, "hidden");
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_value_with_many_sources_is_invalid() {
+        let env = env(r#"
+            KEY = { value = "value", command = "echo value" }
+            "#);
+        assert!(env["KEY"].validate().is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use thiserror::Error;
+
+/// Exit code used when the configuration cannot be loaded.
+pub(crate) const CONFIG_ERROR: i32 = 78;
+
+/// Exit code used when blink(1) device cannot be contacted.
+pub(crate) const DEVICE_ERROR: i32 = 69;
+
+/// Exit code used when a task exceeds its timeout.
+pub(crate) const TIMEOUT: i32 = 124;
+
+/// Exit code used when a task cannot be started, e.g. its command was not found.
+pub(crate) const NOT_STARTED: i32 = 127;
+
+/// Failures of blinc itself, distinguished by exit code.
+#[derive(Debug, Error)]
+pub(crate) enum BlincErr {
+    #[error("invalid configuration")]
+    Config(#[source] anyhow::Error),
+
+    #[error("cannot contact blink(1) device")]
+    Device(#[source] anyhow::Error),
+}
+
+impl BlincErr {
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            BlincErr::Config(_) => CONFIG_ERROR,
+            BlincErr::Device(_) => DEVICE_ERROR,
+        }
+    }
+}
+
+/// Exit code of the error, `1` for errors other than failures of blinc itself.
+pub(crate) fn exit_code(e: &anyhow::Error) -> i32 {
+    e.downcast_ref::<BlincErr>().map_or(1, BlincErr::exit_code)
+}
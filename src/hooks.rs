@@ -0,0 +1,77 @@
+use crate::task::Task;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::fmt;
+
+/// Commands executed around the tasks.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Hooks {
+    before_all: Option<Task>,
+    after_all: Option<Task>,
+    on_success: Option<Task>,
+    on_failure: Option<Task>,
+    finally: Option<Task>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hook {
+    /// Runs before the tasks, tasks are skipped when it fails.
+    BeforeAll,
+    /// Runs after the tasks, unless `BeforeAll` failed.
+    AfterAll,
+    /// Runs when no task failed.
+    OnSuccess,
+    /// Runs when any task failed.
+    OnFailure,
+    /// Always runs, as the last one.
+    Finally,
+}
+
+impl Hooks {
+    pub(crate) fn get(&self, hook: Hook) -> Option<&Task> {
+        match hook {
+            Hook::BeforeAll => self.before_all.as_ref(),
+            Hook::AfterAll => self.after_all.as_ref(),
+            Hook::OnSuccess => self.on_success.as_ref(),
+            Hook::OnFailure => self.on_failure.as_ref(),
+            Hook::Finally => self.finally.as_ref(),
+        }
+    }
+
+    pub(crate) fn all(&self) -> impl Iterator<Item = &Task> {
+        [
+            &self.before_all,
+            &self.after_all,
+            &self.on_success,
+            &self.on_failure,
+            &self.finally,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    pub(crate) fn all_mut(&mut self) -> impl Iterator<Item = &mut Task> {
+        [
+            &mut self.before_all,
+            &mut self.after_all,
+            &mut self.on_success,
+            &mut self.on_failure,
+            &mut self.finally,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hook = match self {
+            Hook::BeforeAll => "before_all",
+            Hook::AfterAll => "after_all",
+            Hook::OnSuccess => "on_success",
+            Hook::OnFailure => "on_failure",
+            Hook::Finally => "finally",
+        };
+        write!(f, "{hook}")
+    }
+}
@@ -0,0 +1,627 @@
+use crate::config::Config;
+use crate::error::BlincErr;
+use crate::quiet::{Quiet, QuietMode};
+use crate::report::Outcome;
+use anyhow::{anyhow, Result};
+use blinkrs::{Blinkers, Color, Message};
+use chrono::Local;
+use log::debug;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const FADE: Duration = Duration::from_millis(500);
+
+const STALE_FADE: Duration = Duration::from_secs(3);
+
+const FLASH: Duration = Duration::from_millis(100);
+
+const RESTART_FLASHES: usize = 3;
+
+const DIM_BRIGHTNESS: f64 = 0.125;
+
+const QUIET_CHECK: Duration = Duration::from_secs(60);
+
+const ESCALATION_FLASH: Duration = Duration::from_millis(500);
+
+/// Settings of the light, from `[light]` section.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct LightSettings {
+    hold: Option<String>,
+    idle: Option<Idle>,
+    keep_failure: Option<bool>,
+    sticky_failure: Option<bool>,
+    escalate: Option<String>,
+}
+
+/// What happens with the result after it's held.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Idle {
+    Off,
+    Dim,
+}
+
+impl LightSettings {
+    pub(crate) const DEFAULT: LightSettings = LightSettings {
+        hold: None,
+        idle: None,
+        keep_failure: None,
+        sticky_failure: None,
+        escalate: None,
+    };
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.hold()?;
+        self.escalate()?;
+        Ok(())
+    }
+
+    /// Names of the options which are set but only apply in long-running modes, where the light
+    /// stays on between runs.
+    pub(crate) fn long_running_only(&self) -> Vec<&'static str> {
+        [
+            ("hold", self.hold.is_some()),
+            ("idle", self.idle.is_some()),
+            ("keep_failure", self.keep_failure.is_some()),
+            ("sticky_failure", self.sticky_failure.is_some()),
+            ("escalate", self.escalate.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect()
+    }
+
+    /// How long the result is shown before the light goes idle, forever by default.
+    fn hold(&self) -> Result<Option<Duration>> {
+        Ok(self
+            .hold
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()?)
+    }
+
+    /// Light is turned off when idle, unless it's configured to dim.
+    fn idle(&self) -> Idle {
+        self.idle.unwrap_or(Idle::Off)
+    }
+
+    /// Failure isn't held for limited time, it stays until it's acknowledged.
+    fn keep_failure(&self) -> bool {
+        self.keep_failure.unwrap_or(false)
+    }
+
+    /// Failure stays lit, also after later successful runs, until it's acknowledged.
+    fn sticky_failure(&self) -> bool {
+        self.sticky_failure.unwrap_or(false)
+    }
+
+    /// Time after which sticky failure which wasn't acknowledged starts flashing.
+    fn escalate(&self) -> Result<Option<Duration>> {
+        Ok(self
+            .escalate
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()?)
+    }
+}
+
+/// State shown by the light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum State {
+    /// Tasks are running, pending transition is played.
+    Pending,
+    /// Tasks finished, color of the outcome is shown.
+    Done(Outcome),
+    /// Running tasks were cancelled to start them again, quick flashes are played once.
+    Restarting,
+    /// Shown result is out of date, its color slowly fades into the stale color.
+    Stale,
+    /// Result was acknowledged. Light is turned off, unless sticky failure was hiding the result
+    /// of a later run, which is shown instead.
+    Acknowledged,
+    /// Light is turned off.
+    Off,
+}
+
+/// Drives blink(1) device from a separate thread, which holds the device open until the light is
+/// finished.
+pub(crate) struct Light {
+    sender: Sender<State>,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl Light {
+    /// Opens the device and starts playing pending transition.
+    pub(crate) fn start(config: &Config) -> Result<Self> {
+        Light::open(config, State::Pending)
+    }
+
+    /// Opens the device and shows the initial state.
+    pub(crate) fn open(config: &Config, state: State) -> Result<Self> {
+        let blinkers = Blinkers::new().map_err(|e| BlincErr::Device(e.into()))?;
+        let palette = Palette::new(config)?;
+        let (sender, receiver) = channel();
+        debug!("opening light with {:?}", state);
+        let handle = thread::spawn(move || show(&blinkers, &palette, &receiver, state));
+        Ok(Self { sender, handle })
+    }
+
+    pub(crate) fn show(&self, state: State) -> Result<()> {
+        debug!("showing {:?}", state);
+        self.sender
+            .send(state)
+            .map_err(|_| BlincErr::Device(anyhow!("light stopped")))?;
+        Ok(())
+    }
+
+    /// Handle for showing states from threads which outlive the borrow of the light.
+    pub(crate) fn notifier(&self) -> Notifier {
+        Notifier(self.sender.clone())
+    }
+
+    pub(crate) fn finish(self, outcome: Outcome) -> Result<()> {
+        debug!("notifying about {:?}", outcome);
+        self.show(State::Done(outcome))?;
+        self.close()
+    }
+
+    /// Waits until the last state is sent to the device.
+    pub(crate) fn close(self) -> Result<()> {
+        drop(self.sender);
+        self.handle.join().expect("cannot join light thread")?;
+        Ok(())
+    }
+}
+
+/// Shows states on the light, it doesn't keep the device open.
+#[derive(Clone)]
+pub(crate) struct Notifier(Sender<State>);
+
+impl Notifier {
+    pub(crate) fn show(&self, state: State) -> Result<()> {
+        debug!("showing {:?}", state);
+        self.0
+            .send(state)
+            .map_err(|_| BlincErr::Device(anyhow!("light stopped")))?;
+        Ok(())
+    }
+}
+
+/// Failure which wasn't acknowledged yet, together with the latest outcome it hides.
+#[derive(Default)]
+struct Sticky {
+    since: Option<Instant>,
+    latest: Option<Outcome>,
+}
+
+/// What the light shows for the received state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shown {
+    /// State is shown as it is.
+    State(State),
+    /// Failure which wasn't acknowledged since the instant hides the state.
+    StickyFailure(Instant),
+}
+
+impl Sticky {
+    /// Keeps track of the sticky failure, which is enabled with `sticky_failure`. Acknowledgement
+    /// reveals the latest outcome hidden by the failure, or turns the light off.
+    fn apply(&mut self, state: State, sticky_failure: bool, now: Instant) -> Shown {
+        match state {
+            State::Done(outcome) => {
+                self.latest = Some(outcome);
+                if sticky_failure && outcome == Outcome::Failure && self.since.is_none() {
+                    self.since = Some(now);
+                }
+            }
+            State::Acknowledged => {
+                let hidden = self.since.take().and(self.latest);
+                return match hidden.filter(|outcome| *outcome != Outcome::Failure) {
+                    Some(outcome) => Shown::State(State::Done(outcome)),
+                    None => Shown::State(State::Off),
+                };
+            }
+            _ => {}
+        }
+        match (state, self.since) {
+            (State::Done(_) | State::Stale, Some(since)) => Shown::StickyFailure(since),
+            _ => Shown::State(state),
+        }
+    }
+}
+
+/// Shows received states until the light is finished.
+fn show(
+    blinkers: &Blinkers,
+    palette: &Palette,
+    receiver: &Receiver<State>,
+    mut state: State,
+) -> Result<()> {
+    let mut sticky = Sticky::default();
+    loop {
+        let next = match sticky.apply(state, palette.sticky_failure, Instant::now()) {
+            Shown::StickyFailure(since) => sticky_failure(blinkers, palette, receiver, since)?,
+            Shown::State(State::Pending) => pending(blinkers, palette, receiver)?,
+            Shown::State(State::Done(outcome)) => {
+                send(
+                    blinkers,
+                    palette,
+                    Message::Fade(palette.color(outcome), FADE),
+                )?;
+                let hold = match outcome {
+                    Outcome::Failure if palette.keep_failure => None,
+                    _ => palette.hold,
+                };
+                held(blinkers, palette, receiver, palette.color(outcome), hold)?
+            }
+            Shown::State(State::Restarting) => {
+                restarting(blinkers, palette)?;
+                receiver.recv().ok()
+            }
+            Shown::State(State::Stale) => {
+                send(blinkers, palette, Message::Fade(palette.stale, STALE_FADE))?;
+                held(blinkers, palette, receiver, palette.stale, palette.hold)?
+            }
+            Shown::State(State::Acknowledged | State::Off) => {
+                send(blinkers, palette, Message::Off)?;
+                receiver.recv().ok()
+            }
+        };
+        match next {
+            Some(next) => state = next,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Plays pending transition until the next state is received.
+fn pending(
+    blinkers: &Blinkers,
+    palette: &Palette,
+    receiver: &Receiver<State>,
+) -> Result<Option<State>> {
+    loop {
+        let colors = if palette.is_solid()? {
+            1
+        } else {
+            palette.pending.len()
+        };
+        for &color in palette.pending.iter().take(colors) {
+            send(blinkers, palette, Message::Fade(color, FADE))?;
+            match receiver.recv_timeout(FADE) {
+                Ok(State::Pending) | Err(RecvTimeoutError::Timeout) => {}
+                Ok(state) => return Ok(Some(state)),
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Waits for the next state, making the light idle when the result was held long enough.
+fn held(
+    blinkers: &Blinkers,
+    palette: &Palette,
+    receiver: &Receiver<State>,
+    color: Color,
+    hold: Option<Duration>,
+) -> Result<Option<State>> {
+    let Some(hold) = hold else {
+        return wait(blinkers, palette, receiver, Some(color));
+    };
+    match wait_for(blinkers, palette, receiver, color, hold)? {
+        Ok(state) => return Ok(Some(state)),
+        Err(RecvTimeoutError::Timeout) => {}
+        Err(RecvTimeoutError::Disconnected) => return Ok(None),
+    }
+    debug!("result held for {:?}, going idle", hold);
+    let idle = match palette.idle {
+        Idle::Off => None,
+        Idle::Dim => Some(dim(color)),
+    };
+    match idle {
+        Some(color) => send(blinkers, palette, Message::Fade(color, FADE))?,
+        None => send(blinkers, palette, Message::Off)?,
+    }
+    wait(blinkers, palette, receiver, idle)
+}
+
+/// Waits for the next state, showing the color again regularly, so quiet hours which start or end
+/// in the meantime apply to it.
+fn wait(
+    blinkers: &Blinkers,
+    palette: &Palette,
+    receiver: &Receiver<State>,
+    color: Option<Color>,
+) -> Result<Option<State>> {
+    let Some(color) = color.filter(|_| palette.quiet.is_some()) else {
+        return Ok(receiver.recv().ok());
+    };
+    loop {
+        match receiver.recv_timeout(QUIET_CHECK) {
+            Ok(state) => return Ok(Some(state)),
+            Err(RecvTimeoutError::Timeout) => send(blinkers, palette, Message::Fade(color, FADE))?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(None),
+        }
+    }
+}
+
+/// Waits for the next state at most for the timeout, showing the color again regularly like
+/// `wait` does.
+fn wait_for(
+    blinkers: &Blinkers,
+    palette: &Palette,
+    receiver: &Receiver<State>,
+    color: Color,
+    timeout: Duration,
+) -> Result<Result<State, RecvTimeoutError>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let check = match palette.quiet {
+            Some(_) => remaining.min(QUIET_CHECK),
+            None => remaining,
+        };
+        match receiver.recv_timeout(check) {
+            Err(RecvTimeoutError::Timeout) if check < remaining => {
+                send(blinkers, palette, Message::Fade(color, FADE))?
+            }
+            result => return Ok(result),
+        }
+    }
+}
+
+/// Shows the sticky failure, flashing it when it isn't acknowledged within the escalation time.
+fn sticky_failure(
+    blinkers: &Blinkers,
+    palette: &Palette,
+    receiver: &Receiver<State>,
+    since: Instant,
+) -> Result<Option<State>> {
+    send(blinkers, palette, Message::Fade(palette.failure, FADE))?;
+    let Some(escalate) = palette.escalate else {
+        return wait(blinkers, palette, receiver, Some(palette.failure));
+    };
+    let remaining = escalate.saturating_sub(since.elapsed());
+    match wait_for(blinkers, palette, receiver, palette.failure, remaining)? {
+        Ok(state) => return Ok(Some(state)),
+        Err(RecvTimeoutError::Timeout) => {}
+        Err(RecvTimeoutError::Disconnected) => return Ok(None),
+    }
+    debug!("failure not acknowledged for {:?}, escalating", escalate);
+    loop {
+        let off = if palette.is_solid()? {
+            Message::Immediate(palette.failure)
+        } else {
+            Message::Off
+        };
+        for message in [Message::Immediate(palette.failure), off] {
+            send(blinkers, palette, message)?;
+            match receiver.recv_timeout(ESCALATION_FLASH) {
+                Ok(state) => return Ok(Some(state)),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            }
+        }
+    }
+}
+
+fn dim(color: Color) -> Color {
+    scale(color, DIM_BRIGHTNESS)
+}
+
+fn scale(color: Color, brightness: f64) -> Color {
+    let (red, green, blue) = color.rgb();
+    let scale = |value: u8| (f64::from(value) * brightness) as u8;
+    Color::Three(scale(red), scale(green), scale(blue))
+}
+
+fn restarting(blinkers: &Blinkers, palette: &Palette) -> Result<()> {
+    if palette.is_solid()? {
+        return Ok(());
+    }
+    let color = palette.pending.first().copied().unwrap_or(Color::Blue);
+    for _ in 0..RESTART_FLASHES {
+        send(blinkers, palette, Message::Immediate(color))?;
+        thread::sleep(FLASH);
+        send(blinkers, palette, Message::Off)?;
+        thread::sleep(FLASH);
+    }
+    Ok(())
+}
+
+/// Sends the message adjusted to quiet hours.
+fn send(blinkers: &Blinkers, palette: &Palette, message: Message) -> Result<()> {
+    blinkers
+        .send(palette.quieted(message)?)
+        .map_err(|e| BlincErr::Device(e.into()))?;
+    Ok(())
+}
+
+struct Palette {
+    pending: Vec<Color>,
+    success: Color,
+    partial: Color,
+    failure: Color,
+    stale: Color,
+    hold: Option<Duration>,
+    idle: Idle,
+    keep_failure: bool,
+    sticky_failure: bool,
+    escalate: Option<Duration>,
+    quiet: Option<Quiet>,
+}
+
+impl Palette {
+    fn new(config: &Config) -> Result<Self> {
+        let settings = config.light();
+        Ok(Self {
+            pending: config.pending().iter().map(Color::from).collect(),
+            success: config.success().into(),
+            partial: config.partial().into(),
+            failure: config.failure().into(),
+            stale: config.stale().into(),
+            hold: settings.hold()?,
+            idle: settings.idle(),
+            keep_failure: settings.keep_failure(),
+            sticky_failure: settings.sticky_failure(),
+            escalate: settings.escalate()?,
+            quiet: config.schedule().and_then(|s| s.quiet()).cloned(),
+        })
+    }
+
+    /// Whether animations are replaced with solid colors in the current quiet window.
+    fn is_solid(&self) -> Result<bool> {
+        Ok(self.mode()? == Some(QuietMode::Solid))
+    }
+
+    fn mode(&self) -> Result<Option<QuietMode>> {
+        match &self.quiet {
+            Some(quiet) => quiet.mode(Local::now().time()),
+            None => Ok(None),
+        }
+    }
+
+    /// Turns the light off or lowers its brightness in quiet hours.
+    fn quieted(&self, message: Message) -> Result<Message> {
+        let Some(quiet) = &self.quiet else {
+            return Ok(message);
+        };
+        let brightness = match self.mode()? {
+            Some(QuietMode::Off) => return Ok(Message::Off),
+            Some(QuietMode::Dim) => DIM_BRIGHTNESS,
+            Some(QuietMode::Solid) | None => 1.0,
+        } * quiet.brightness(Local::now().time())?;
+        Ok(match message {
+            Message::Fade(color, duration) => Message::Fade(scale(color, brightness), duration),
+            Message::Immediate(color) => Message::Immediate(scale(color, brightness)),
+            Message::Off => Message::Off,
+        })
+    }
+
+    fn color(&self, outcome: Outcome) -> Color {
+        match outcome {
+            Outcome::Success => self.success,
+            Outcome::Partial => self.partial,
+            Outcome::Failure => self.failure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{dim, Idle, LightSettings, Shown, State, Sticky};
+    use crate::report::Outcome;
+    use crate::Result;
+    use blinkrs::Color;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_result_is_held_forever_by_default() -> Result<()> {
+        let settings = LightSettings::DEFAULT;
+        assert_eq!(settings.hold()?, None);
+        assert_eq!(settings.idle(), Idle::Off);
+        assert!(!settings.keep_failure());
+        assert!(!settings.sticky_failure());
+        assert_eq!(settings.escalate()?, None);
+        assert!(settings.long_running_only().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_light_settings_are_parsed() -> Result<()> {
+        let settings: LightSettings = toml::from_str(
+            r#"
+            hold = "10m"
+            idle = "dim"
+            keep_failure = true
+            sticky_failure = true
+            escalate = "30m"
+            "#,
+        )?;
+        assert_eq!(settings.hold()?, Some(Duration::from_secs(600)));
+        assert_eq!(settings.idle(), Idle::Dim);
+        assert!(settings.keep_failure());
+        assert!(settings.sticky_failure());
+        assert_eq!(settings.escalate()?, Some(Duration::from_secs(1800)));
+        assert_eq!(
+            settings.long_running_only(),
+            ["hold", "idle", "keep_failure", "sticky_failure", "escalate"]
+        );
+        let settings: LightSettings = toml::from_str(r#"hold = "forever""#)?;
+        assert!(settings.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sticky_failure_hides_later_results_until_acknowledged() {
+        let mut sticky = Sticky::default();
+        let failed = Instant::now();
+        let later = failed + Duration::from_secs(60);
+        let success = State::Done(Outcome::Success);
+        let failure = State::Done(Outcome::Failure);
+        assert_eq!(
+            sticky.apply(failure, true, failed),
+            Shown::StickyFailure(failed)
+        );
+        assert_eq!(
+            sticky.apply(State::Pending, true, later),
+            Shown::State(State::Pending)
+        );
+        assert_eq!(
+            sticky.apply(failure, true, later),
+            Shown::StickyFailure(failed)
+        );
+        assert_eq!(
+            sticky.apply(success, true, later),
+            Shown::StickyFailure(failed)
+        );
+        assert_eq!(
+            sticky.apply(State::Stale, true, later),
+            Shown::StickyFailure(failed)
+        );
+        assert_eq!(
+            sticky.apply(State::Acknowledged, true, later),
+            Shown::State(success)
+        );
+        assert_eq!(sticky.apply(success, true, later), Shown::State(success));
+    }
+
+    #[test]
+    fn test_acknowledged_failure_turns_light_off() {
+        let mut sticky = Sticky::default();
+        let now = Instant::now();
+        let failure = State::Done(Outcome::Failure);
+        assert_eq!(sticky.apply(failure, true, now), Shown::StickyFailure(now));
+        assert_eq!(
+            sticky.apply(State::Acknowledged, true, now),
+            Shown::State(State::Off)
+        );
+        assert_eq!(
+            sticky.apply(State::Acknowledged, true, now),
+            Shown::State(State::Off)
+        );
+    }
+
+    #[test]
+    fn test_failure_is_not_sticky_by_default() {
+        let mut sticky = Sticky::default();
+        let now = Instant::now();
+        let failure = State::Done(Outcome::Failure);
+        let success = State::Done(Outcome::Success);
+        assert_eq!(sticky.apply(failure, false, now), Shown::State(failure));
+        assert_eq!(sticky.apply(success, false, now), Shown::State(success));
+        assert_eq!(
+            sticky.apply(State::Acknowledged, false, now),
+            Shown::State(State::Off)
+        );
+    }
+
+    #[test]
+    fn test_dimmed_color_keeps_hue() {
+        assert_eq!(dim(Color::Red).rgb(), (0x1f, 0, 0));
+        assert_eq!(dim(Color::Three(0x80, 0x40, 0)).rgb(), (0x10, 0x08, 0));
+    }
+}
@@ -0,0 +1,243 @@
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+static TASK_CGROUPS: AtomicUsize = AtomicUsize::new(0);
+
+/// Resource limits and niceness applied to the task process before it's executed.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub(crate) struct Limits {
+    nice: Option<i32>,
+    memory: Option<String>,
+    cpu_time: Option<String>,
+    open_files: Option<u64>,
+}
+
+impl Limits {
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.memory()?;
+        self.cpu_time()?;
+        Ok(())
+    }
+
+    fn memory(&self) -> Result<Option<u64>> {
+        self.memory.as_deref().map(parse_size).transpose()
+    }
+
+    fn cpu_time(&self) -> Result<Option<Duration>> {
+        Ok(self
+            .cpu_time
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()?)
+    }
+
+    /// Cgroup of the task limiting its memory, `None` without `memory` limit.
+    pub(crate) fn cgroup(&self) -> Result<Option<Cgroup>> {
+        let Some(memory) = self.memory()? else {
+            return Ok(None);
+        };
+        let cgroup = Cgroup::create(memory).context(
+            "cannot limit memory of the task, it needs cgroup v2 memory controller delegated to \
+            blinc, e.g. with `systemd-run --user --scope -p Delegate=yes cargo blinc`",
+        )?;
+        Ok(Some(cgroup))
+    }
+
+    /// Makes the command set the limits in the child process, right before it's executed.
+    pub(crate) fn apply(&self, command: &mut Command) -> Result<()> {
+        let nice = self.nice;
+        let mut limits = Vec::new();
+        if let Some(cpu_time) = self.cpu_time()? {
+            // at least a second, zero would kill the process immediately; the hard limit is higher,
+            // otherwise the kernel sends SIGKILL instead of SIGXCPU
+            let seconds = cpu_time.as_secs().max(1);
+            limits.push((libc::RLIMIT_CPU, seconds, seconds + 1));
+        }
+        if let Some(open_files) = self.open_files {
+            limits.push((libc::RLIMIT_NOFILE, open_files, open_files));
+        }
+        if nice.is_none() && limits.is_empty() {
+            return Ok(());
+        }
+        // SAFETY: the closure runs in the forked child, it only calls async-signal-safe functions
+        // and doesn't allocate.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                for &(resource, soft, hard) in &limits {
+                    let limit = libc::rlimit {
+                        rlim_cur: soft,
+                        rlim_max: hard,
+                    };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Parses size in bytes with optional `K`, `M` or `G` binary suffix, e.g. `512M`.
+fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier: u64 = match size[digits.len()..].to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        unit => bail!("unknown size unit `{unit}` in `{size}`"),
+    };
+    let Ok(value) = digits.trim().parse::<u64>() else {
+        bail!("invalid size `{size}`");
+    };
+    let Some(bytes) = value.checked_mul(multiplier) else {
+        bail!("size `{size}` is too big");
+    };
+    Ok(bytes)
+}
+
+/// Cgroup v2 of a single task run, with limited memory of the task and its child processes. It's
+/// removed when dropped, after the processes exit.
+#[derive(Debug)]
+pub(crate) struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    fn create(memory: u64) -> Result<Self> {
+        let parent = memory_cgroup().as_ref().map_err(|e| anyhow!("{e}"))?;
+        let id = TASK_CGROUPS.fetch_add(1, Ordering::SeqCst);
+        let path = parent.join(format!("blinc-task-{}-{id}", process::id()));
+        fs::create_dir(&path).with_context(|| format!("cannot create cgroup {path:?}"))?;
+        let cgroup = Self { path };
+        fs::write(cgroup.path.join("memory.max"), memory.to_string())?;
+        // swapping would slow the task down instead of stopping it, swap may also be disabled
+        let _ = fs::write(cgroup.path.join("memory.swap.max"), "0");
+        debug!("created {:?} with {} bytes of memory", cgroup.path, memory);
+        Ok(cgroup)
+    }
+
+    /// Makes the child process join the cgroup, right before it's executed.
+    pub(crate) fn join(&self, command: &mut Command) -> Result<()> {
+        let procs = CString::new(self.path.join("cgroup.procs").as_os_str().as_bytes())?;
+        // SAFETY: the closure runs in the forked child, it only calls async-signal-safe functions
+        // and doesn't allocate.
+        unsafe {
+            command.pre_exec(move || {
+                let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // `0` stands for the writing process
+                let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+                let error = io::Error::last_os_error();
+                libc::close(fd);
+                if written != 1 {
+                    return Err(error);
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the kernel OOM killer killed any process in the cgroup.
+    pub(crate) fn oom_killed(&self) -> bool {
+        fs::read_to_string(self.path.join("memory.events"))
+            .ok()
+            .and_then(|events| parse_oom_kills(&events))
+            .is_some_and(|kills| kills > 0)
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir(&self.path) {
+            debug!("cannot remove cgroup {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Cgroup of blinc with memory controller enabled for its children, which are cgroups of tasks.
+fn memory_cgroup() -> &'static Result<PathBuf, String> {
+    static CGROUP: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+    CGROUP.get_or_init(|| enable_memory_controller().map_err(|e| format!("{e:#}")))
+}
+
+fn enable_memory_controller() -> Result<PathBuf> {
+    let cgroup = fs::read_to_string("/proc/self/cgroup")?;
+    let Some(path) = cgroup.lines().find_map(|line| line.strip_prefix("0::")) else {
+        bail!("cgroup v2 isn't available");
+    };
+    let dir = Path::new(CGROUP_ROOT).join(path.trim_start_matches('/'));
+    let control = dir.join("cgroup.subtree_control");
+    let enabled = fs::read_to_string(&control)
+        .with_context(|| format!("cannot read {control:?}"))?
+        .split_whitespace()
+        .any(|controller| controller == "memory");
+    if !enabled {
+        // cgroups with processes cannot enable controllers for their children, so blinc moves
+        // itself into a leaf child first
+        let leaf = dir.join("blinc");
+        fs::create_dir_all(&leaf).with_context(|| format!("cannot create cgroup {leaf:?}"))?;
+        fs::write(leaf.join("cgroup.procs"), process::id().to_string())
+            .with_context(|| format!("cannot move blinc to cgroup {leaf:?}"))?;
+        fs::write(&control, "+memory")
+            .with_context(|| format!("cannot enable memory controller in {dir:?}"))?;
+    }
+    Ok(dir)
+}
+
+fn parse_oom_kills(events: &str) -> Option<u64> {
+    events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_oom_kills, parse_size};
+
+    #[test]
+    fn test_sizes_are_parsed_with_units() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("4K").unwrap(), 4096);
+        assert_eq!(parse_size("512M").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_size("2T").is_err());
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("99999999999G").is_err());
+    }
+
+    #[test]
+    fn test_oom_kills_are_read_from_memory_events() {
+        let events = "low 0\nhigh 0\nmax 3\noom 1\noom_kill 2\noom_group_kill 0\n";
+        assert_eq!(parse_oom_kills(events), Some(2));
+        assert_eq!(parse_oom_kills("low 0\n"), None);
+    }
+}
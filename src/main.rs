@@ -3,32 +3,116 @@
 
 #![deny(missing_docs)]
 
-use anyhow::Result;
-use args::Opt;
+use anyhow::{anyhow, Result};
+use args::{Cmd, Opt};
 use blinc::Blinc;
 use config::Config;
+use error::BlincErr;
+use light::{Light, State};
 use log::debug;
+use schedule::Schedule;
 use std::process;
 use structopt::StructOpt;
+use watch::Source;
 
 mod args;
 mod blinc;
+mod cancel;
 mod colors;
 mod config;
+mod daemon;
+mod environment;
+mod error;
+mod hooks;
+mod light;
+mod limits;
+mod quiet;
+mod report;
+mod sandbox;
+mod schedule;
 mod task;
+mod vars;
+mod watch;
 
 #[cfg(test)]
 mod testutils;
 
-fn main() -> Result<()> {
+fn main() {
     env_logger::init();
-    let Opt::Blinc { init, config } = Opt::from_args();
-    if init.is_some() {
+    if let Err(e) = run() {
+        eprintln!("Error: {e:?}");
+        process::exit(error::exit_code(&e));
+    }
+}
+
+fn run() -> Result<()> {
+    let Opt::Blinc {
+        init,
+        config,
+        tag,
+        profile,
+        args,
+        cmd,
+    } = Opt::from_args();
+    if let Some(path) = init {
         debug!("init argument passed, initializing config");
-        // can unwrap because it's checked earlier
-        Config::default().store(init.unwrap())?;
+        Config::default().store(path)?;
         process::exit(0);
     }
-    Blinc::new(Config::get(config)?).exec_tasks()?;
-    Ok(())
+    let mut config = Config::get(config)?;
+    config.set_profile(&profile);
+    let (tasks, args) = match &cmd {
+        Some(Cmd::Run {
+            tasks,
+            args: run_args,
+        }) => (tasks.clone(), [args, run_args.clone()].concat()),
+        Some(Cmd::Exec { cmd, args }) => return Blinc::new(config)?.exec_command(cmd, args),
+        Some(Cmd::Ack { socket }) => {
+            if !daemon::acknowledge(socket)? {
+                Light::open(&config, State::Off)?.close()?;
+            }
+            return Ok(());
+        }
+        _ => (Vec::new(), args),
+    };
+    config.select(&tasks, &tag)?;
+    config.pass_args(&args);
+    match cmd {
+        Some(Cmd::Watch {
+            fifo,
+            socket,
+            ack_socket,
+        }) => {
+            config.disable_stdin();
+            let source = match (fifo, socket) {
+                (Some(fifo), _) => Source::Fifo(fifo),
+                (None, Some(socket)) => Source::Socket(socket),
+                (None, None) => Source::Files,
+            };
+            Blinc::new(config)?.watch(&source, ack_socket.as_deref())
+        }
+        Some(Cmd::Daemon { socket }) => {
+            config.disable_stdin();
+            daemon::serve(&Blinc::new(config)?, &socket)
+        }
+        Some(Cmd::Schedule {
+            every,
+            cron,
+            ack_socket,
+        }) => {
+            config.disable_stdin();
+            let schedule = match (every, cron) {
+                (Some(every), _) => Schedule::every(&every),
+                (None, Some(cron)) => Schedule::cron(&cron),
+                (None, None) => config.schedule().cloned().unwrap_or_default(),
+            };
+            schedule.validate().map_err(BlincErr::Config)?;
+            if !schedule.is_periodic() {
+                let e = anyhow!("schedule needs `--every`, `--cron` or `[schedule]` section");
+                return Err(BlincErr::Config(e).into());
+            }
+            Blinc::new(config)?.schedule(&schedule, ack_socket.as_deref())
+        }
+        _ => Blinc::new(config)?.exec_tasks(),
+    }
 }
@@ -0,0 +1,196 @@
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveTime, Timelike};
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+/// Quiet hours of the light, from `[schedule.quiet]` section.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub(crate) struct Quiet {
+    windows: Option<Vec<Window>>,
+    evening: Option<Evening>,
+}
+
+/// Time window in which the light is quiet. It wraps around midnight when `to` is earlier than
+/// `from`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Window {
+    from: String,
+    to: String,
+    mode: QuietMode,
+}
+
+/// How the light behaves in the quiet window.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum QuietMode {
+    /// Light is turned off.
+    Off,
+    /// Colors are shown at low brightness.
+    Dim,
+    /// Animations are replaced with solid colors.
+    Solid,
+}
+
+const MORNING: &str = "06:00";
+
+/// Brightness decreasing linearly from full at `from` to `brightness` at `to`, where it stays until
+/// `morning`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Evening {
+    from: String,
+    to: String,
+    brightness: f64,
+    morning: Option<String>,
+}
+
+impl Quiet {
+    pub(crate) fn validate(&self) -> Result<()> {
+        for window in self.windows.iter().flatten() {
+            window.range()?;
+        }
+        if let Some(evening) = &self.evening {
+            evening.range()?;
+            evening.morning()?;
+            if !(0.0..=1.0).contains(&evening.brightness) {
+                bail!("evening brightness has to be between 0 and 1");
+            }
+        }
+        Ok(())
+    }
+
+    /// Mode of the first window containing the time, `None` outside quiet windows.
+    pub(crate) fn mode(&self, time: NaiveTime) -> Result<Option<QuietMode>> {
+        for window in self.windows.iter().flatten() {
+            if contains(window.range()?, time) {
+                return Ok(Some(window.mode));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Brightness following the evening curve, the lowest one through the night and full during
+    /// the day.
+    pub(crate) fn brightness(&self, time: NaiveTime) -> Result<f64> {
+        let Some(evening) = &self.evening else {
+            return Ok(1.0);
+        };
+        let (from, to) = evening.range()?;
+        if !contains((from, to), time) {
+            return Ok(if contains((to, evening.morning()?), time) {
+                evening.brightness
+            } else {
+                1.0
+            });
+        }
+        let length = seconds_between(from, to);
+        let elapsed = seconds_between(from, time);
+        let progress = f64::from(elapsed) / f64::from(length.max(1));
+        Ok(1.0 - (1.0 - evening.brightness) * progress)
+    }
+}
+
+impl Window {
+    fn range(&self) -> Result<(NaiveTime, NaiveTime)> {
+        Ok((parse_time(&self.from)?, parse_time(&self.to)?))
+    }
+}
+
+impl Evening {
+    fn range(&self) -> Result<(NaiveTime, NaiveTime)> {
+        Ok((parse_time(&self.from)?, parse_time(&self.to)?))
+    }
+
+    /// Time when the brightness is full again, `06:00` by default.
+    fn morning(&self) -> Result<NaiveTime> {
+        parse_time(self.morning.as_deref().unwrap_or(MORNING))
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .with_context(|| format!("invalid time `{time}`, expected HH:MM"))
+}
+
+fn contains((from, to): (NaiveTime, NaiveTime), time: NaiveTime) -> bool {
+    if from <= to {
+        from <= time && time < to
+    } else {
+        time >= from || time < to
+    }
+}
+
+/// Seconds from the first time to the second one, wrapping around midnight.
+fn seconds_between(from: NaiveTime, to: NaiveTime) -> u32 {
+    const DAY: u32 = 24 * 60 * 60;
+    (to.num_seconds_from_midnight() + DAY - from.num_seconds_from_midnight()) % DAY
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Quiet, QuietMode};
+    use crate::Result;
+    use chrono::NaiveTime;
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_first_window_containing_time_sets_mode() -> Result<()> {
+        let quiet: Quiet = toml::from_str(
+            r#"
+            windows = [
+                { from = "23:00", to = "07:00", mode = "off" },
+                { from = "20:00", to = "23:30", mode = "solid" },
+            ]
+            "#,
+        )?;
+        quiet.validate()?;
+        assert_eq!(quiet.mode(at(2, 0))?, Some(QuietMode::Off));
+        assert_eq!(quiet.mode(at(23, 15))?, Some(QuietMode::Off));
+        assert_eq!(quiet.mode(at(21, 0))?, Some(QuietMode::Solid));
+        assert_eq!(quiet.mode(at(7, 0))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_evening_brightness_decreases_linearly() -> Result<()> {
+        let quiet: Quiet =
+            toml::from_str(r#"evening = { from = "18:00", to = "22:00", brightness = 0.2 }"#)?;
+        quiet.validate()?;
+        assert_eq!(quiet.brightness(at(12, 0))?, 1.0);
+        assert_eq!(quiet.brightness(at(18, 0))?, 1.0);
+        assert!((quiet.brightness(at(20, 0))? - 0.6).abs() < 1e-9);
+        assert_eq!(quiet.brightness(at(22, 0))?, 0.2);
+        assert_eq!(quiet.brightness(at(3, 0))?, 0.2);
+        assert_eq!(quiet.brightness(at(6, 0))?, 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_evening_brightness_is_full_again_in_the_morning() -> Result<()> {
+        let quiet: Quiet = toml::from_str(
+            r#"evening = { from = "18:00", to = "22:00", brightness = 0.2, morning = "08:00" }"#,
+        )?;
+        quiet.validate()?;
+        assert_eq!(quiet.brightness(at(7, 59))?, 0.2);
+        assert_eq!(quiet.brightness(at(8, 0))?, 1.0);
+        assert_eq!(quiet.brightness(at(17, 59))?, 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_quiet_hours_are_rejected() -> Result<()> {
+        let quiet: Quiet =
+            toml::from_str(r#"windows = [{ from = "late", to = "07:00", mode = "dim" }]"#)?;
+        assert!(quiet.validate().is_err());
+        let quiet: Quiet =
+            toml::from_str(r#"evening = { from = "18:00", to = "22:00", brightness = 2.0 }"#)?;
+        assert!(quiet.validate().is_err());
+        let quiet: Quiet = toml::from_str(
+            r#"evening = { from = "18:00", to = "22:00", brightness = 0.2, morning = "dawn" }"#,
+        )?;
+        assert!(quiet.validate().is_err());
+        Ok(())
+    }
+}
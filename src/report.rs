@@ -0,0 +1,235 @@
+use crate::task::Task;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Status {
+    Success,
+    Failure,
+    Timeout,
+    OutOfMemory,
+    Cancelled,
+    Skipped,
+}
+
+impl Status {
+    pub(crate) fn failed(self) -> bool {
+        matches!(
+            self,
+            Status::Failure | Status::Timeout | Status::OutOfMemory
+        )
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            Status::Success => "success",
+            Status::Failure => "failure",
+            Status::Timeout => "timeout",
+            Status::OutOfMemory => "oom",
+            Status::Cancelled => "cancelled",
+            Status::Skipped => "skipped",
+        };
+        f.pad(status)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Success,
+    Partial,
+    Failure,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self {
+            Outcome::Success => "success",
+            Outcome::Partial => "partial",
+            Outcome::Failure => "failure",
+        };
+        f.pad(outcome)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TaskReport {
+    name: String,
+    status: Status,
+    allow_failure: bool,
+    code: i32,
+    duration: Option<Duration>,
+}
+
+impl TaskReport {
+    pub(crate) fn new(task: &Task, status: Status, code: i32, duration: Duration) -> Self {
+        Self {
+            name: task.name(),
+            status,
+            allow_failure: task.allow_failure(),
+            code,
+            duration: Some(duration),
+        }
+    }
+
+    pub(crate) fn skipped(task: &Task) -> Self {
+        Self {
+            name: task.name(),
+            status: Status::Skipped,
+            allow_failure: task.allow_failure(),
+            code: 0,
+            duration: None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn status(&self) -> Status {
+        self.status
+    }
+
+    pub(crate) fn code(&self) -> i32 {
+        self.code
+    }
+
+    /// Duration of the task, `None` when it was skipped.
+    pub(crate) fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Failure of the task which is not allowed to fail.
+    pub(crate) fn is_failure(&self) -> bool {
+        self.status.failed() && !self.allow_failure
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Report {
+    tasks: Vec<TaskReport>,
+}
+
+impl Report {
+    pub(crate) fn add(&mut self, task: TaskReport) {
+        self.tasks.push(task);
+    }
+
+    pub(crate) fn tasks(&self) -> &[TaskReport] {
+        &self.tasks
+    }
+
+    pub(crate) fn status(&self, name: &str) -> Option<Status> {
+        self.tasks.iter().find(|t| t.name == name).map(|t| t.status)
+    }
+
+    pub(crate) fn outcome(&self) -> Outcome {
+        if self.tasks.iter().any(TaskReport::is_failure) {
+            Outcome::Failure
+        } else if self.tasks.iter().any(|t| t.status.failed()) {
+            Outcome::Partial
+        } else {
+            Outcome::Success
+        }
+    }
+
+    /// Name of the first failed task which is not allowed to fail.
+    pub(crate) fn failed_task(&self) -> Option<&str> {
+        self.tasks
+            .iter()
+            .find(|t| t.is_failure())
+            .map(TaskReport::name)
+    }
+
+    /// Exit code of the first failed task, `0` when no task failed. Task which failed despite
+    /// exiting with `0`, e.g. because of `fail_if_output_matches`, results with `1`.
+    pub(crate) fn code(&self) -> i32 {
+        self.tasks
+            .iter()
+            .find(|t| t.is_failure())
+            .map_or(0, |t| if t.code == 0 { 1 } else { t.code })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .tasks()
+            .iter()
+            .map(|t| t.name().len())
+            .chain(Some("task".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "{:width$}  {:7}  duration", "task", "status")?;
+        for task in self.tasks() {
+            let duration = task
+                .duration
+                .map_or_else(|| "-".to_string(), |d| format!("{:.2}s", d.as_secs_f64()));
+            let allowed = if task.status().failed() && task.allow_failure {
+                " (allowed)"
+            } else {
+                ""
+            };
+            writeln!(
+                f,
+                "{:width$}  {:7}  {duration}{allowed}",
+                task.name(),
+                task.status()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Outcome, Report, Status, TaskReport};
+    use crate::task::Task;
+    use std::time::Duration;
+
+    fn report(statuses: &[(Status, bool)]) -> Report {
+        let mut report = Report::default();
+        for (status, allow_failure) in statuses {
+            let code = i32::from(status.failed());
+            let mut task = TaskReport::new(&Task::new("true", &[]), *status, code, Duration::ZERO);
+            task.allow_failure = *allow_failure;
+            report.add(task);
+        }
+        report
+    }
+
+    #[test]
+    fn test_outcome_is_success_when_all_tasks_succeed() {
+        let report = report(&[(Status::Success, false), (Status::Success, true)]);
+        assert_eq!(report.outcome(), Outcome::Success);
+    }
+
+    #[test]
+    fn test_outcome_is_partial_when_only_allowed_tasks_fail() {
+        let report = report(&[(Status::Success, false), (Status::Failure, true)]);
+        assert_eq!(report.outcome(), Outcome::Partial);
+    }
+
+    #[test]
+    fn test_outcome_is_failure_when_required_task_fails() {
+        let report = report(&[(Status::Failure, false), (Status::Failure, true)]);
+        assert_eq!(report.outcome(), Outcome::Failure);
+    }
+
+    #[test]
+    fn test_code_is_taken_from_first_required_failure() {
+        let report = report(&[(Status::Failure, true), (Status::Timeout, false)]);
+        assert_eq!(report.code(), 1);
+        assert_eq!(report.outcome(), Outcome::Failure);
+    }
+
+    #[test]
+    fn test_summary_lists_every_task() {
+        let report = report(&[(Status::Success, false), (Status::Failure, true)]);
+        let summary = report.to_string();
+        assert!(summary.starts_with("task  status   duration\n"));
+        assert!(summary.contains("true  success  0.00s\n"));
+        assert!(summary.contains("true  failure  0.00s (allowed)\n"));
+    }
+}
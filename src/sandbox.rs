@@ -0,0 +1,195 @@
+use anyhow::{Context, Result};
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::ptr;
+
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+
+const AT_RECURSIVE: libc::c_uint = 0x8000;
+
+/// Runs the task in user and network namespaces, optionally with read-only access to files
+/// outside the workspace and temporary directory.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum Sandbox {
+    Enabled(bool),
+    Options { read_only: Option<bool> },
+}
+
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+/// `ifreq` structure limited to the interface name and flags.
+#[repr(C)]
+struct IfReq {
+    name: [libc::c_char; libc::IFNAMSIZ],
+    flags: libc::c_short,
+    _pad: [u8; 22],
+}
+
+impl Sandbox {
+    pub(crate) fn enabled(&self) -> bool {
+        match self {
+            Sandbox::Enabled(enabled) => *enabled,
+            Sandbox::Options { .. } => true,
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        match self {
+            Sandbox::Enabled(_) => false,
+            Sandbox::Options { read_only } => read_only.unwrap_or(false),
+        }
+    }
+
+    /// Makes the command enter new namespaces in the child process, right before it's executed.
+    pub(crate) fn apply(&self, command: &mut Command, workspace: &Path) -> Result<()> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        // everything is allocated here, the child process can't allocate after fork
+        let uid = unsafe { libc::geteuid() };
+        let gid = unsafe { libc::getegid() };
+        let uid_map = format!("{uid} {uid} 1");
+        let gid_map = format!("{gid} {gid} 1");
+        let writable = if self.read_only() {
+            [workspace.to_path_buf(), env::temp_dir()]
+                .iter()
+                .filter(|dir| dir.is_dir())
+                .map(|dir| CString::new(dir.as_os_str().as_bytes()))
+                .collect::<Result<Vec<_>, _>>()
+                .context("invalid writable directory")?
+        } else {
+            Vec::new()
+        };
+        let read_only = self.read_only();
+        // SAFETY: the closure runs in the forked child, it only calls async-signal-safe functions
+        // and doesn't allocate.
+        unsafe {
+            command.pre_exec(move || {
+                check(libc::unshare(
+                    libc::CLONE_NEWUSER | libc::CLONE_NEWNET | libc::CLONE_NEWNS,
+                ))?;
+                write_file(c"/proc/self/uid_map", uid_map.as_bytes())?;
+                write_file(c"/proc/self/setgroups", b"deny")?;
+                write_file(c"/proc/self/gid_map", gid_map.as_bytes())?;
+                loopback_up()?;
+                if read_only {
+                    remount_read_only(&writable)?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Makes all mounts read-only except the writable directories.
+unsafe fn remount_read_only(writable: &[CString]) -> io::Result<()> {
+    let root = c"/";
+    let flags = libc::MS_REC | libc::MS_PRIVATE;
+    check(libc::mount(
+        ptr::null(),
+        root.as_ptr(),
+        ptr::null(),
+        flags,
+        ptr::null(),
+    ))?;
+    for dir in writable {
+        let flags = libc::MS_BIND | libc::MS_REC;
+        check(libc::mount(
+            dir.as_ptr(),
+            dir.as_ptr(),
+            ptr::null(),
+            flags,
+            ptr::null(),
+        ))?;
+    }
+    mount_setattr(root, MOUNT_ATTR_RDONLY, 0)?;
+    for dir in writable {
+        mount_setattr(dir, 0, MOUNT_ATTR_RDONLY)?;
+    }
+    // working directory still points to the mount from before the bind
+    let mut cwd = [0 as libc::c_char; libc::PATH_MAX as usize];
+    if !libc::getcwd(cwd.as_mut_ptr(), cwd.len()).is_null() {
+        check(libc::chdir(cwd.as_ptr()))?;
+    }
+    Ok(())
+}
+
+unsafe fn mount_setattr(path: &CStr, attr_set: u64, attr_clr: u64) -> io::Result<()> {
+    let attr = MountAttr {
+        attr_set,
+        attr_clr,
+        propagation: 0,
+        userns_fd: 0,
+    };
+    let result = libc::syscall(
+        libc::SYS_mount_setattr,
+        libc::AT_FDCWD,
+        path.as_ptr(),
+        AT_RECURSIVE,
+        &attr as *const MountAttr,
+        std::mem::size_of::<MountAttr>(),
+    );
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Brings up the loopback interface of the new network namespace, so tasks can still use
+/// `localhost`.
+unsafe fn loopback_up() -> io::Result<()> {
+    let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+    check(socket)?;
+    let mut request = IfReq {
+        name: [0; libc::IFNAMSIZ],
+        flags: 0,
+        _pad: [0; 22],
+    };
+    request.name[0] = b'l' as libc::c_char;
+    request.name[1] = b'o' as libc::c_char;
+    let mut result = libc::ioctl(socket, libc::SIOCGIFFLAGS as _, &mut request);
+    if result == 0 {
+        request.flags |= libc::IFF_UP as libc::c_short;
+        result = libc::ioctl(socket, libc::SIOCSIFFLAGS as _, &request);
+    }
+    let error = io::Error::last_os_error();
+    libc::close(socket);
+    if result != 0 {
+        return Err(error);
+    }
+    Ok(())
+}
+
+unsafe fn write_file(path: &CStr, content: &[u8]) -> io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    check(fd)?;
+    let written = libc::write(fd, content.as_ptr().cast(), content.len());
+    let error = io::Error::last_os_error();
+    libc::close(fd);
+    if written < 0 {
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use crate::quiet::Quiet;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Local};
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Schedule of periodic runs, either an interval or a cron expression.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub(crate) struct Schedule {
+    every: Option<String>,
+    cron: Option<String>,
+    quiet: Option<Quiet>,
+}
+
+impl Schedule {
+    pub(crate) fn every(interval: &str) -> Self {
+        Self {
+            every: Some(interval.to_string()),
+            cron: None,
+            quiet: None,
+        }
+    }
+
+    pub(crate) fn cron(expression: &str) -> Self {
+        Self {
+            every: None,
+            cron: Some(expression.to_string()),
+            quiet: None,
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.every.is_some() && self.cron.is_some() {
+            bail!("schedule can have either `every` or `cron`, not both");
+        }
+        self.interval()?;
+        self.cron_schedule()?;
+        if let Some(quiet) = &self.quiet {
+            quiet.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Whether the schedule defines when to run the tasks.
+    pub(crate) fn is_periodic(&self) -> bool {
+        self.every.is_some() || self.cron.is_some()
+    }
+
+    /// Quiet hours of the light, applied in every mode.
+    pub(crate) fn quiet(&self) -> Option<&Quiet> {
+        self.quiet.as_ref()
+    }
+
+    fn interval(&self) -> Result<Option<Duration>> {
+        Ok(self
+            .every
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()?)
+    }
+
+    fn cron_schedule(&self) -> Result<Option<cron::Schedule>> {
+        self.cron
+            .as_deref()
+            .map(|expression| {
+                cron::Schedule::from_str(expression)
+                    .map_err(|e| anyhow!("invalid cron expression `{expression}`: {e}"))
+            })
+            .transpose()
+    }
+
+    /// Time of the run following the previous one, `None` for the first run means it's due
+    /// immediately.
+    pub(crate) fn next(
+        &self,
+        previous: Option<DateTime<Local>>,
+    ) -> Result<Option<DateTime<Local>>> {
+        if let Some(interval) = self.interval()? {
+            return Ok(previous.map(|previous| previous + interval));
+        }
+        let Some(cron) = self.cron_schedule()? else {
+            bail!("schedule needs `every` or `cron`");
+        };
+        let after = previous.unwrap_or_else(Local::now);
+        let next = cron
+            .after(&after)
+            .next()
+            .ok_or_else(|| anyhow!("cron expression has no upcoming runs"))?;
+        Ok(Some(next))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Schedule;
+    use crate::Result;
+    use chrono::{Local, TimeZone, Timelike};
+    use std::time::Duration;
+
+    #[test]
+    fn test_interval_schedule_runs_immediately_and_then_periodically() -> Result<()> {
+        let schedule = Schedule::every("15m");
+        assert_eq!(schedule.next(None)?, None);
+        let previous = Local::now();
+        let next = schedule.next(Some(previous))?;
+        assert_eq!(next, Some(previous + Duration::from_secs(15 * 60)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cron_schedule_runs_at_matching_time() -> Result<()> {
+        let schedule = Schedule::cron("0 30 2 * * *");
+        let previous = Local.with_ymd_and_hms(2023, 9, 14, 12, 0, 0).unwrap();
+        let next = schedule.next(Some(previous))?.unwrap();
+        assert_eq!((next.hour(), next.minute()), (2, 30));
+        assert!(next > previous);
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_with_interval_and_cron_is_invalid() {
+        let schedule: Schedule = toml::from_str(
+            r#"
+            every = "15m"
+            cron = "0 30 2 * * *"
+            "#,
+        )
+        .unwrap();
+        assert!(schedule.validate().is_err());
+        assert!(Schedule::cron("tomorrow").validate().is_err());
+    }
+}
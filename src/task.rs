@@ -1,20 +1,90 @@
-use anyhow::Result;
+use crate::cancel::{self, Cancel};
+use crate::environment::EnvValue;
+use crate::error::TIMEOUT;
+use crate::limits::{Cgroup, Limits};
+use crate::report::Status;
+use crate::sandbox::Sandbox;
+use crate::vars::{self, Vars};
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::debug;
+use regex::Regex;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::Child;
 use std::process::Command;
 use std::process::ExitStatus;
+use std::process::Stdio;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const ARGS_PLACEHOLDER: &str = "{args}";
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Task {
+    name: Option<String>,
     cmd: String,
     args: Option<Vec<String>>,
+    allow_failure: Option<bool>,
+    group: Option<String>,
+    needs: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+    success_codes: Option<Vec<i32>>,
+    fail_if_output_matches: Option<String>,
+    success_if_output_matches: Option<String>,
+    accepts_args: Option<bool>,
+    timeout: Option<String>,
+    cwd: Option<String>,
+    env: Option<HashMap<String, EnvValue>>,
+    limits: Option<Limits>,
+    sandbox: Option<Sandbox>,
+    stdin: Option<Stdin>,
+    watch: Option<Vec<String>>,
+}
+
+/// Source of the task's standard input.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum Stdin {
+    Mode(StdinMode),
+    File { file: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum StdinMode {
+    Null,
+    Inherit,
 }
 
 impl Task {
     pub(crate) fn new(cmd: &str, args: &[&str]) -> Self {
         Self {
+            name: None,
             cmd: cmd.to_string(),
             args: Some(args.iter().map(|&arg| arg.to_string()).collect()),
+            allow_failure: None,
+            group: None,
+            needs: None,
+            tags: None,
+            success_codes: None,
+            fail_if_output_matches: None,
+            success_if_output_matches: None,
+            accepts_args: None,
+            timeout: None,
+            cwd: None,
+            env: None,
+            limits: None,
+            sandbox: None,
+            stdin: None,
+            watch: None,
         }
     }
 
@@ -26,7 +96,758 @@ impl Task {
         self.args.clone().unwrap_or_default()
     }
 
-    pub(crate) fn run(&self) -> Result<ExitStatus> {
-        Ok(Command::new(self.command()).args(self.args()).status()?)
+    /// Name of the task, defaults to the command with its arguments.
+    pub(crate) fn name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        let mut name = self.cmd.clone();
+        for arg in self.args() {
+            name.push(' ');
+            name.push_str(&arg);
+        }
+        name
+    }
+
+    pub(crate) fn allow_failure(&self) -> bool {
+        self.allow_failure.unwrap_or(false)
+    }
+
+    /// Name of the group of tasks executed at the same time.
+    pub(crate) fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Names of the tasks which have to succeed before this task runs.
+    pub(crate) fn needs(&self) -> &[String] {
+        self.needs.as_deref().unwrap_or_default()
+    }
+
+    pub(crate) fn tags(&self) -> &[String] {
+        self.tags.as_deref().unwrap_or_default()
+    }
+
+    /// Substitutes `{args}` placeholder with passed arguments. Without the placeholder, arguments
+    /// are appended when the task accepts them. Passed arguments aren't expanded and don't change
+    /// the default name of the task.
+    pub(crate) fn pass_args(&mut self, extra: &[String]) {
+        self.name = Some(self.name());
+        let extra: Vec<String> = extra.iter().map(|arg| vars::escape(arg)).collect();
+        let extra = extra.as_slice();
+        let args = self.args();
+        if args.iter().any(|arg| arg.contains(ARGS_PLACEHOLDER)) {
+            let args = args.into_iter().flat_map(|arg| {
+                if arg == ARGS_PLACEHOLDER {
+                    extra.to_vec()
+                } else {
+                    vec![arg.replace(ARGS_PLACEHOLDER, &extra.join(" "))]
+                }
+            });
+            self.args = Some(args.collect());
+        } else if self.accepts_args.unwrap_or(false) {
+            self.args = Some([args, extra.to_vec()].concat());
+        }
+    }
+
+    /// Sets the standard input of the task unless it's configured explicitly.
+    pub(crate) fn default_stdin(&mut self, stdin: Stdin) {
+        self.stdin.get_or_insert(stdin);
+    }
+
+    /// Exit codes treated as success, defaults to `0`.
+    pub(crate) fn success_codes(&self) -> &[i32] {
+        self.success_codes.as_deref().unwrap_or(&[0])
+    }
+
+    /// Checks if output matching patterns, timeout and limits are valid.
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.fail_pattern()?;
+        self.success_pattern()?;
+        self.timeout()?;
+        self.watch_globs()?;
+        if let Some(limits) = &self.limits {
+            limits.validate()?;
+        }
+        for value in self.env.iter().flat_map(HashMap::values) {
+            value.validate()?;
+        }
+        Ok(())
+    }
+
+    fn timeout(&self) -> Result<Option<Duration>> {
+        Ok(self
+            .timeout
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()?)
+    }
+
+    /// Checks if any of the paths, relative to the workspace root, matches `watch` globs. Task
+    /// without globs watches all files.
+    pub(crate) fn watches(&self, paths: &[&Path]) -> Result<bool> {
+        let Some(globs) = self.watch_globs()? else {
+            return Ok(true);
+        };
+        Ok(paths.iter().any(|path| globs.is_match(path)))
+    }
+
+    fn watch_globs(&self) -> Result<Option<GlobSet>> {
+        let Some(patterns) = &self.watch else {
+            return Ok(None);
+        };
+        let mut globs = GlobSetBuilder::new();
+        for pattern in patterns {
+            globs.add(Glob::new(pattern)?);
+        }
+        Ok(Some(globs.build()?))
+    }
+
+    fn fail_pattern(&self) -> Result<Option<Regex>> {
+        Ok(self
+            .fail_if_output_matches
+            .as_deref()
+            .map(Regex::new)
+            .transpose()?)
+    }
+
+    fn success_pattern(&self) -> Result<Option<Regex>> {
+        Ok(self
+            .success_if_output_matches
+            .as_deref()
+            .map(Regex::new)
+            .transpose()?)
+    }
+
+    fn captures_output(&self) -> bool {
+        self.fail_if_output_matches.is_some() || self.success_if_output_matches.is_some()
+    }
+
+    /// Builds the command with variables expanded in the command, arguments, working directory and
+    /// environment variables.
+    fn to_command(
+        &self,
+        vars: &Vars,
+        env: &[(&str, String)],
+        cgroup: Option<&Cgroup>,
+    ) -> Result<Command> {
+        let mut command = Command::new(vars.expand_with(self.command(), env)?);
+        for arg in self.args() {
+            command.arg(vars.expand_with(&arg, env)?);
+        }
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(vars.expand_with(cwd, env)?);
+        }
+        for (key, value) in self.env.iter().flatten() {
+            command.env(key, value.resolve(vars)?);
+        }
+        command.envs(env.iter().map(|(key, value)| (key, value)));
+        match &self.stdin {
+            None | Some(Stdin::Mode(StdinMode::Inherit)) => {}
+            Some(Stdin::Mode(StdinMode::Null)) => {
+                command.stdin(Stdio::null());
+            }
+            Some(Stdin::File { file }) => {
+                let path = vars.config_dir().join(vars.expand(file)?);
+                let file = File::open(&path)
+                    .with_context(|| format!("cannot open stdin file {path:?}"))?;
+                command.stdin(file);
+            }
+        }
+        // the cgroup is joined before the sandbox creates new user namespace
+        if let Some(cgroup) = cgroup {
+            cgroup.join(&mut command)?;
+        }
+        if let Some(limits) = &self.limits {
+            limits.apply(&mut command)?;
+        }
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.apply(&mut command, &vars.workspace_dir())?;
+        }
+        Ok(command)
+    }
+
+    pub(crate) fn run(&self, vars: &Vars) -> Result<Exit> {
+        self.run_cancellable(vars, &Cancel::default())
+    }
+
+    /// Runs the task, which is killed together with its child processes when cancelled.
+    pub(crate) fn run_cancellable(&self, vars: &Vars, cancel: &Cancel) -> Result<Exit> {
+        self.start(vars, &[], None, cancel)
+    }
+
+    /// Runs the task with additional environment variables.
+    pub(crate) fn run_with_env(&self, vars: &Vars, env: &[(&str, String)]) -> Result<Exit> {
+        self.start(vars, env, None, &Cancel::default())
+    }
+
+    /// Runs the task prefixing every line of its output with the task name.
+    pub(crate) fn run_prefixed(&self, vars: &Vars, cancel: &Cancel) -> Result<Exit> {
+        self.start(vars, &[], Some(format!("[{}] ", self.name())), cancel)
+    }
+
+    fn start(
+        &self,
+        vars: &Vars,
+        env: &[(&str, String)],
+        prefix: Option<String>,
+        cancel: &Cancel,
+    ) -> Result<Exit> {
+        if prefix.is_some() || self.captures_output() {
+            return self.exec(vars, env, prefix, cancel);
+        }
+        let cgroup = self.cgroup()?;
+        let mut child = self.spawn(&mut self.to_command(vars, env, cgroup.as_ref())?)?;
+        let status = self.wait(&mut child, cancel)?;
+        let oom_killed = cgroup.as_ref().is_some_and(Cgroup::oom_killed);
+        self.exit(status, "", oom_killed, cancel)
+    }
+
+    fn exec(
+        &self,
+        vars: &Vars,
+        env: &[(&str, String)],
+        prefix: Option<String>,
+        cancel: &Cancel,
+    ) -> Result<Exit> {
+        let cgroup = self.cgroup()?;
+        let mut command = self.to_command(vars, env, cgroup.as_ref())?;
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = self.spawn(&mut command)?;
+        let prefix = prefix.unwrap_or_default();
+        let capture = self.captures_output();
+        let stdout = child
+            .stdout
+            .take()
+            .map(|out| forward(out, &prefix, capture, io::stdout));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|err| forward(err, &prefix, capture, io::stderr));
+        let status = self.wait(&mut child, cancel)?;
+        let output: String = stdout
+            .into_iter()
+            .chain(stderr)
+            .map(|handle| handle.join().expect("cannot join output thread"))
+            .collect();
+        let oom_killed = cgroup.as_ref().is_some_and(Cgroup::oom_killed);
+        self.exit(status, &output, oom_killed, cancel)
+    }
+
+    /// Cgroup limiting memory of the task, `None` without `memory` limit.
+    fn cgroup(&self) -> Result<Option<Cgroup>> {
+        match &self.limits {
+            Some(limits) => limits.cgroup(),
+            None => Ok(None),
+        }
+    }
+
+    fn spawn(&self, command: &mut Command) -> Result<Child> {
+        let sandboxed = self.sandbox.as_ref().is_some_and(Sandbox::enabled);
+        command.spawn().with_context(|| {
+            if sandboxed {
+                format!(
+                    "cannot run sandboxed task `{}`, unprivileged user namespaces may be disabled \
+                    (see `kernel.unprivileged_userns_clone` and `user.max_user_namespaces` sysctls)",
+                    self.name()
+                )
+            } else {
+                format!("cannot run task `{}`", self.name())
+            }
+        })
+    }
+
+    /// Waits for the child process. Kills it with its child processes when it exceeds the timeout
+    /// or when it's cancelled.
+    fn wait(&self, child: &mut Child, cancel: &Cancel) -> Result<Option<ExitStatus>> {
+        let deadline = self.timeout()?.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Some(status));
+            }
+            if cancel.is_cancelled() {
+                debug!("task cancelled, killing it");
+                cancel::kill_tree(child.id())?;
+                return Ok(Some(child.wait()?));
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                debug!("task timed out after {:?}, killing it", self.timeout);
+                cancel::kill_tree(child.id())?;
+                child.wait()?;
+                return Ok(None);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Recognizes processes killed for exceeding their limits. The task ran out of memory when it
+    /// failed after the kernel killed any of its processes for exceeding the `memory` limit.
+    fn limit_exceeded(exit_status: ExitStatus, oom_killed: bool) -> Option<Status> {
+        if oom_killed && !exit_status.success() {
+            return Some(Status::OutOfMemory);
+        }
+        match exit_status.signal()? {
+            libc::SIGXCPU => Some(Status::Timeout),
+            _ => None,
+        }
+    }
+
+    fn exit(
+        &self,
+        exit_status: Option<ExitStatus>,
+        output: &str,
+        oom_killed: bool,
+        cancel: &Cancel,
+    ) -> Result<Exit> {
+        let Some(exit_status) = exit_status else {
+            return Ok(Exit {
+                status: Status::Timeout,
+                exit_status: None,
+            });
+        };
+        if cancel.is_cancelled() {
+            return Ok(Exit {
+                status: Status::Cancelled,
+                exit_status: Some(exit_status),
+            });
+        }
+        if let Some(status) = Task::limit_exceeded(exit_status, oom_killed) {
+            return Ok(Exit {
+                status,
+                exit_status: Some(exit_status),
+            });
+        }
+        let status = if self.fail_pattern()?.is_some_and(|p| p.is_match(output)) {
+            Status::Failure
+        } else if self.success_pattern()?.is_some_and(|p| p.is_match(output)) {
+            Status::Success
+        } else {
+            match exit_status.code() {
+                Some(code) if self.success_codes().contains(&code) => Status::Success,
+                _ => Status::Failure,
+            }
+        };
+        Ok(Exit {
+            status,
+            exit_status: Some(exit_status),
+        })
+    }
+}
+
+/// Result of the task execution.
+#[derive(Debug)]
+pub(crate) struct Exit {
+    status: Status,
+    exit_status: Option<ExitStatus>,
+}
+
+impl Exit {
+    pub(crate) fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Exit code of the process, `128 + signal` when it was killed by a signal. Processes which
+    /// exceeded their timeout or CPU time have the same code.
+    pub(crate) fn code(&self) -> i32 {
+        let Some(exit_status) = self.exit_status.filter(|_| self.status != Status::Timeout) else {
+            return TIMEOUT;
+        };
+        exit_status
+            .code()
+            .or_else(|| exit_status.signal().map(|signal| 128 + signal))
+            .unwrap_or(1)
+    }
+}
+
+/// Forwards lines read from the child process, returns them if `capture` is set.
+fn forward<R, W, F>(read: R, prefix: &str, capture: bool, write: F) -> thread::JoinHandle<String>
+where
+    R: Read + Send + 'static,
+    W: Write,
+    F: Fn() -> W + Send + 'static,
+{
+    let prefix = prefix.to_string();
+    thread::spawn(move || {
+        let mut captured = String::new();
+        let mut reader = BufReader::new(read);
+        let mut buf = Vec::new();
+        // output which isn't valid UTF-8 is forwarded lossily, so the pipe is read until the end
+        while matches!(reader.read_until(b'\n', &mut buf), Ok(read) if read > 0) {
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let _ = writeln!(write(), "{prefix}{line}");
+            if capture {
+                captured.push_str(line);
+                captured.push('\n');
+            }
+            buf.clear();
+        }
+        captured
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Exit, Task};
+    use crate::cancel::Cancel;
+    use crate::error::TIMEOUT;
+    use crate::report::Status;
+    use crate::testutils::init_logger;
+    use crate::vars::Vars;
+    use crate::Result;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn task(config: &str) -> Task {
+        toml::from_str(config).unwrap()
+    }
+
+    #[test]
+    fn test_exit_code_from_success_codes_is_success() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "exit 1"]
+            success_codes = [0, 1]
+            "#,
+        );
+        assert_eq!(task.run(&Vars::default())?.status(), Status::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_failure_pattern_overrides_exit_code() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "echo 'error: broken' >&2"]
+            fail_if_output_matches = "^error:"
+            "#,
+        );
+        assert_eq!(task.run(&Vars::default())?.status(), Status::Failure);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_success_pattern_overrides_exit_code() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "echo 'nothing to do'; exit 1"]
+            success_if_output_matches = "nothing to do"
+            "#,
+        );
+        assert_eq!(
+            task.run_prefixed(&Vars::default(), &Cancel::default())?
+                .status(),
+            Status::Success
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_which_is_not_utf8_is_read_until_the_end() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "printf 'bad \\377\\n'; seq 20000; echo 'all done'; exit 1"]
+            success_if_output_matches = "(?m)^all done$"
+            "#,
+        );
+        let exit = task.run_prefixed(&Vars::default(), &Cancel::default())?;
+        assert_eq!((exit.status(), exit.code()), (Status::Success, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_are_appended_when_task_accepts_them() {
+        let mut task = task(
+            r#"
+            cmd = "cargo"
+            args = ["test"]
+            accepts_args = true
+            "#,
+        );
+        task.pass_args(&["--lib".to_string(), "filter".to_string()]);
+        assert_eq!(task.args(), vec!["test", "--lib", "filter"]);
+        assert_eq!(task.name(), "cargo test");
+    }
+
+    #[test]
+    fn test_args_are_substituted_into_placeholder() {
+        let mut task = task(
+            r#"
+            cmd = "cargo"
+            args = ["test", "{args}", "--", "--filter={args}"]
+            "#,
+        );
+        task.pass_args(&["--lib".to_string(), "filter".to_string()]);
+        assert_eq!(
+            task.args(),
+            vec!["test", "--lib", "filter", "--", "--filter=--lib filter"]
+        );
+    }
+
+    #[test]
+    fn test_passed_args_are_not_expanded() -> Result<()> {
+        init_logger();
+        let mut task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "test \"$0\" = '$${HOME}'"]
+            accepts_args = true
+            "#,
+        );
+        task.pass_args(&["${HOME}".to_string()]);
+        assert_eq!(task.run(&Vars::default())?.status(), Status::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_are_ignored_by_other_tasks() {
+        let mut task = task(
+            r#"
+            cmd = "cargo"
+            args = ["check"]
+            "#,
+        );
+        task.pass_args(&["--lib".to_string()]);
+        assert_eq!(task.args(), vec!["check"]);
+    }
+
+    #[test]
+    fn test_exit_code_of_process_killed_by_signal() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "kill -TERM $$"]
+            "#,
+        );
+        let exit = task.run(&Vars::default())?;
+        assert_eq!(exit.status(), Status::Failure);
+        assert_eq!(exit.code(), 143);
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_exceeding_timeout_is_killed() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sleep"
+            args = ["5"]
+            timeout = "100ms"
+            "#,
+        );
+        let exit = task.run(&Vars::default())?;
+        assert_eq!(exit.status(), Status::Timeout);
+        assert_eq!(exit.code(), 124);
+        Ok(())
+    }
+
+    #[test]
+    fn test_variables_are_expanded_when_task_runs() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "test \"$(pwd)\" = /tmp && test \"$GREETING\" = \"hello ${name}\""]
+            cwd = "${dir}"
+            env = { GREETING = "hello ${name}" }
+            "#,
+        );
+        let vars = HashMap::from([
+            ("dir".to_string(), "/tmp".to_string()),
+            ("name".to_string(), "blinc".to_string()),
+        ]);
+        let exit = task.run(&Vars::new(vars, "/", "dev"))?;
+        assert_eq!(exit.status(), Status::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_limits_are_applied_to_task() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "test $(ulimit -n) = 64 && test $(nice) = 5"]
+            limits = { nice = 5, open_files = 64 }
+            "#,
+        );
+        assert_eq!(task.run(&Vars::default())?.status(), Status::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_exceeding_cpu_time_is_reported_as_timeout() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "while :; do :; done"]
+            limits = { cpu_time = "1s" }
+            "#,
+        );
+        let exit = task.run(&Vars::default())?;
+        assert_eq!((exit.status(), exit.code()), (Status::Timeout, TIMEOUT));
+        Ok(())
+    }
+
+    /// Runs the task with `memory` limit, `None` when cgroup v2 memory controller isn't delegated
+    /// to the tests.
+    fn run_with_memory_limit(task: &Task) -> Result<Option<Exit>> {
+        match task.run(&Vars::default()) {
+            Ok(exit) => Ok(Some(exit)),
+            Err(e) if format!("{e:#}").contains("cannot limit memory") => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[test]
+    fn test_task_exceeding_memory_limit_is_reported_as_oom() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "head -c 256M /dev/zero | tail"]
+            limits = { memory = "32M" }
+            "#,
+        );
+        if let Some(exit) = run_with_memory_limit(&task)? {
+            assert_eq!(exit.status(), Status::OutOfMemory);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_aborted_with_memory_limit_is_not_reported_as_oom() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "kill -ABRT $$"]
+            limits = { memory = "512M" }
+            "#,
+        );
+        if let Some(exit) = run_with_memory_limit(&task)? {
+            assert_eq!(exit.status(), Status::Failure);
+            assert_eq!(exit.code(), 134);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sandboxed_task_has_only_loopback_network() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "test $(tail -n +3 /proc/net/dev | wc -l) = 1 && grep -q lo: /proc/net/dev"]
+            sandbox = true
+            "#,
+        );
+        assert_eq!(task.run(&Vars::default())?.status(), Status::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_sandbox_allows_writing_only_to_workspace() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "! touch /blinc-sandbox && touch target/blinc-sandbox && rm target/blinc-sandbox"]
+            sandbox = { read_only = true }
+            "#,
+        );
+        let vars = Vars::new(HashMap::new(), env!("CARGO_MANIFEST_DIR"), "dev");
+        assert_eq!(task.run(&vars)?.status(), Status::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_reads_stdin_from_file() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "grep"
+            args = ["-q", "fn main"]
+            stdin = { file = "src/main.rs" }
+            "#,
+        );
+        let exit = task.run(&Vars::new(
+            HashMap::new(),
+            env!("CARGO_MANIFEST_DIR"),
+            "dev",
+        ))?;
+        assert_eq!(exit.status(), Status::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_with_null_stdin_does_not_wait_for_input() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "read answer; test -z \"$answer\""]
+            stdin = "null"
+            timeout = "5s"
+            "#,
+        );
+        assert_eq!(task.run(&Vars::default())?.status(), Status::Success);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancelled_task_is_killed() -> Result<()> {
+        init_logger();
+        let task = task(
+            r#"
+            cmd = "sh"
+            args = ["-c", "sleep 30"]
+            "#,
+        );
+        let cancel = Cancel::default();
+        let canceller = cancel.clone();
+        let start = Instant::now();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            canceller.cancel();
+        });
+        let exit = task.run_cancellable(&Vars::default(), &cancel)?;
+        handle.join().unwrap();
+        assert_eq!(exit.status(), Status::Cancelled);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_watches_paths_matching_globs() -> Result<()> {
+        let docs = task(
+            r#"
+            cmd = "cargo"
+            args = ["doc"]
+            watch = ["**/*.md", "src/**/*.rs"]
+            "#,
+        );
+        let all = task(
+            r#"
+            cmd = "cargo"
+            args = ["test"]
+            "#,
+        );
+        assert!(docs.watches(&[Path::new("README.md")])?);
+        assert!(docs.watches(&[Path::new("Cargo.toml"), Path::new("src/task.rs")])?);
+        assert!(!docs.watches(&[Path::new("Cargo.toml")])?);
+        assert!(all.watches(&[Path::new("Cargo.toml")])?);
+        Ok(())
     }
 }
@@ -0,0 +1,217 @@
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Expands `${VAR}` and `${VAR:-default}` references, `$${` is a literal `${`. Variables are looked
+/// up in `[vars]` table, built-in variables and environment variables, in that order.
+#[derive(Debug, Default)]
+pub(crate) struct Vars {
+    vars: HashMap<String, String>,
+    config_dir: PathBuf,
+    profile: String,
+    workspace_root: OnceLock<Option<String>>,
+    git_branch: OnceLock<Option<String>>,
+}
+
+impl Vars {
+    pub(crate) fn new<A: AsRef<Path>>(
+        vars: HashMap<String, String>,
+        config_dir: A,
+        profile: &str,
+    ) -> Self {
+        Self {
+            vars,
+            config_dir: config_dir.as_ref().to_path_buf(),
+            profile: profile.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    /// Root of the cargo workspace, the configuration directory outside of it.
+    pub(crate) fn workspace_dir(&self) -> PathBuf {
+        self.workspace_root
+            .get_or_init(|| workspace_root(&self.config_dir))
+            .as_ref()
+            .map_or_else(|| self.config_dir.clone(), PathBuf::from)
+    }
+
+    pub(crate) fn expand(&self, input: &str) -> Result<String> {
+        self.expand_with(input, &[])
+    }
+
+    /// Expands references also to environment variables which are set only for the task, e.g.
+    /// `BLINC_RESULT` of hooks.
+    pub(crate) fn expand_with(&self, input: &str, task_env: &[(&str, String)]) -> Result<String> {
+        let mut expanded = String::new();
+        let mut rest = input;
+        while let Some(start) = rest.find("${") {
+            if let Some(text) = rest[..start].strip_suffix('$') {
+                expanded.push_str(text);
+                expanded.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            expanded.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else {
+                bail!("unclosed variable reference in `{}`", input);
+            };
+            expanded.push_str(&self.resolve(&rest[start + 2..start + end], task_env)?);
+            rest = &rest[start + end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    fn resolve(&self, reference: &str, task_env: &[(&str, String)]) -> Result<String> {
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        match (self.lookup(name, task_env), default) {
+            (Some(value), Some(default)) if value.is_empty() => Ok(default.to_string()),
+            (Some(value), _) => Ok(value),
+            (None, Some(default)) => Ok(default.to_string()),
+            (None, None) => Err(anyhow!("undefined variable `{}`", name)),
+        }
+    }
+
+    fn lookup(&self, name: &str, task_env: &[(&str, String)]) -> Option<String> {
+        if let Some(value) = self.vars.get(name) {
+            return Some(value.clone());
+        }
+        match name {
+            "workspace_root" => Some(self.workspace_dir().to_string_lossy().to_string()),
+            "config_dir" => Some(self.config_dir.to_string_lossy().to_string()),
+            "git_branch" => self
+                .git_branch
+                .get_or_init(|| git_branch(&self.config_dir))
+                .clone(),
+            "profile" => Some(self.profile.clone()),
+            _ => task_env
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.clone())
+                .or_else(|| env::var(name).ok()),
+        }
+    }
+}
+
+/// Escapes the text, so it isn't expanded, e.g. arguments passed through from the command line.
+pub(crate) fn escape(input: &str) -> String {
+    input.replace("${", "$${")
+}
+
+fn workspace_root(config_dir: &Path) -> Option<String> {
+    let manifest = output(Command::new("cargo").current_dir(config_dir).args([
+        "locate-project",
+        "--workspace",
+        "--message-format",
+        "plain",
+    ]))?;
+    let root = Path::new(&manifest).parent()?;
+    Some(root.to_string_lossy().to_string())
+}
+
+fn git_branch(config_dir: &Path) -> Option<String> {
+    output(
+        Command::new("git")
+            .current_dir(config_dir)
+            .args(["rev-parse", "--abbrev-ref", "HEAD"]),
+    )
+}
+
+fn output(command: &mut Command) -> Option<String> {
+    debug!("resolving variable with {:?}", command);
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, Vars};
+    use crate::Result;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    fn vars() -> Vars {
+        let vars = HashMap::from([("target".to_string(), "x86_64".to_string())]);
+        Vars::new(vars, "/home/user/project", "release")
+    }
+
+    #[test]
+    fn test_user_and_builtin_variables_are_expanded() -> Result<()> {
+        let expanded = vars().expand("${config_dir}/target/${target}/${profile}")?;
+        assert_eq!(expanded, "/home/user/project/target/x86_64/release");
+        Ok(())
+    }
+
+    #[test]
+    fn test_environment_variables_are_expanded() -> Result<()> {
+        let expanded = vars().expand("home: ${HOME}")?;
+        assert_eq!(expanded, format!("home: {}", std::env::var("HOME")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_is_used_for_undefined_variable() -> Result<()> {
+        let expanded = vars().expand("${BLINC_UNDEFINED_VARIABLE:-fallback}")?;
+        assert_eq!(expanded, "fallback");
+        Ok(())
+    }
+
+    #[test]
+    fn test_undefined_variable_is_an_error() {
+        let err = vars().expand("${BLINC_UNDEFINED_VARIABLE}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "undefined variable `BLINC_UNDEFINED_VARIABLE`"
+        );
+    }
+
+    #[test]
+    fn test_text_without_variables_is_unchanged() -> Result<()> {
+        assert_eq!(vars().expand("$HOME {args}")?, "$HOME {args}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_escaped_reference_is_not_expanded() -> Result<()> {
+        let expanded = vars().expand("for f in *; do echo $${f}; done; $${#x} ${target}")?;
+        assert_eq!(expanded, "for f in *; do echo ${f}; done; ${#x} x86_64");
+        assert_eq!(vars().expand(&escape("${f} $${g}"))?, "${f} $${g}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_variables_of_task_environment_are_expanded() -> Result<()> {
+        let env = [("BLINC_RESULT", "failure".to_string())];
+        let expanded = vars().expand_with("result: ${BLINC_RESULT}", &env)?;
+        assert_eq!(expanded, "result: failure");
+        Ok(())
+    }
+
+    #[test]
+    fn test_workspace_root_is_located_from_config_dir() -> Result<()> {
+        let crate_dir = env!("CARGO_MANIFEST_DIR");
+        let vars = Vars::new(HashMap::new(), Path::new(crate_dir).join("src"), "debug");
+        assert_eq!(vars.expand("${workspace_root}")?, crate_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn test_workspace_root_is_config_dir_outside_of_workspace() -> Result<()> {
+        assert_eq!(vars().expand("${workspace_root}")?, "/home/user/project");
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+use crate::daemon;
+use anyhow::{anyhow, bail, Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::debug;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Settings of the watch mode.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Watch {
+    ignore: Option<Vec<String>>,
+    debounce: Option<String>,
+    stale: Option<bool>,
+}
+
+impl Watch {
+    pub(crate) const DEFAULT: Watch = Watch {
+        ignore: None,
+        debounce: None,
+        stale: None,
+    };
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.debounce()?;
+        self.ignore_globs(Path::new("."))?;
+        Ok(())
+    }
+
+    /// Time without changes after which a burst of changes is reported, 200ms by default.
+    fn debounce(&self) -> Result<Duration> {
+        Ok(self
+            .debounce
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()?
+            .unwrap_or(DEBOUNCE))
+    }
+
+    /// Whether results of long-running modes are marked stale when files change, `false` by
+    /// default.
+    pub(crate) fn stale(&self) -> bool {
+        self.stale.unwrap_or(false)
+    }
+
+    /// Matcher of files ignored in the root directory, built from `.gitignore`,
+    /// `.git/info/exclude` and additional `ignore` globs.
+    fn ignore_globs(&self, root: &Path) -> Result<Gitignore> {
+        let mut builder = GitignoreBuilder::new(root);
+        for file in [root.join(".gitignore"), root.join(".git/info/exclude")] {
+            if let Some(e) = builder.add(&file) {
+                debug!("cannot read {:?}: {}", file, e);
+            }
+        }
+        builder.add_line(None, ".git/")?;
+        for glob in self.ignore.iter().flatten() {
+            builder.add_line(None, glob)?;
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Source of changes which trigger runs.
+#[derive(Debug)]
+pub(crate) enum Source {
+    /// Changes of files in the workspace, watched recursively.
+    Files,
+    /// Lines written to the named pipe, created when it doesn't exist.
+    Fifo(PathBuf),
+    /// Lines sent to the Unix socket.
+    Socket(PathBuf),
+}
+
+/// Change which triggers a run.
+enum Trigger {
+    Paths(Vec<PathBuf>),
+    All,
+}
+
+/// Waits for changes of files which aren't ignored, reported by the source.
+pub(crate) struct Watcher {
+    receiver: Receiver<Trigger>,
+    root: PathBuf,
+    ignored: Gitignore,
+    global_ignored: Gitignore,
+    debounce: Duration,
+    // dropping the watcher stops watching
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl Watcher {
+    pub(crate) fn new(root: &Path, watch: &Watch, source: &Source) -> Result<Self> {
+        let root = root.canonicalize()?;
+        let (sender, receiver) = channel();
+        let watcher = match source {
+            Source::Files => Some(watch_files(&root, sender)?),
+            Source::Fifo(path) => {
+                listen_fifo(path, &root, sender)?;
+                None
+            }
+            Source::Socket(path) => {
+                listen_socket(path, &root, sender)?;
+                None
+            }
+        };
+        let (global_ignored, e) = Gitignore::global();
+        if let Some(e) = e {
+            debug!("cannot read global excludes file: {}", e);
+        }
+        Ok(Self {
+            receiver,
+            ignored: watch.ignore_globs(&root)?,
+            global_ignored,
+            root,
+            debounce: watch.debounce()?,
+            _watcher: watcher,
+        })
+    }
+
+    /// Blocks until files change. Changes are collected until no new change arrives within the
+    /// debounce period, so a burst of saves is reported once. Returns `None` when the trigger
+    /// didn't specify changed files.
+    pub(crate) fn changes(&self) -> Result<Option<Vec<PathBuf>>> {
+        let mut changed = Some(Vec::new());
+        while changed.as_ref().is_some_and(Vec::is_empty) {
+            let trigger = self.receiver.recv()?;
+            self.collect(trigger, &mut changed);
+        }
+        loop {
+            match self.receiver.recv_timeout(self.debounce) {
+                Ok(trigger) => self.collect(trigger, &mut changed),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("watcher stopped")),
+            }
+        }
+        debug!("changed files: {:?}", changed);
+        Ok(changed)
+    }
+
+    fn collect(&self, trigger: Trigger, changed: &mut Option<Vec<PathBuf>>) {
+        let (Trigger::Paths(paths), Some(changed)) = (trigger, changed.as_mut()) else {
+            *changed = None;
+            return;
+        };
+        for path in paths {
+            if !self.is_ignored(&path) && !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+    }
+
+    /// Checks the path against `.gitignore` files of the directories containing it, deeper ones
+    /// taking precedence, and then against the global excludes file.
+    fn is_ignored(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+        let is_dir = path.is_dir();
+        let mut dirs = vec![self.root.clone()];
+        for component in relative.parent().into_iter().flat_map(Path::components) {
+            let dir = dirs[dirs.len() - 1].join(component);
+            dirs.push(dir);
+        }
+        for dir in dirs.iter().skip(1).rev() {
+            let (nested, _) = Gitignore::new(dir.join(".gitignore"));
+            match nested.matched_path_or_any_parents(path, is_dir) {
+                Match::None => {}
+                matched => return matched.is_ignore(),
+            }
+        }
+        match self.ignored.matched_path_or_any_parents(path, is_dir) {
+            Match::None => self
+                .global_ignored
+                .matched_path_or_any_parents(relative, is_dir)
+                .is_ignore(),
+            matched => matched.is_ignore(),
+        }
+    }
+}
+
+fn watch_files(root: &Path, sender: Sender<Trigger>) -> Result<RecommendedWatcher> {
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+            Ok(event) => {
+                let _ = sender.send(Trigger::Paths(event.paths));
+            }
+            Err(e) => eprintln!("Error: cannot watch files: {e:?}"),
+        })?;
+    debug!("watching {:?}", root);
+    watcher.watch(root, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
+
+/// Reads triggers from the named pipe. It's opened also for writing, so it isn't closed when
+/// writers go away.
+fn listen_fifo(path: &Path, root: &Path, sender: Sender<Trigger>) -> Result<()> {
+    if !path.exists() {
+        debug!("creating fifo {:?}", path);
+        let fifo = CString::new(path.as_os_str().as_bytes())?;
+        if unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) } != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("cannot create fifo {}", path.display()));
+        }
+    } else if !fs::metadata(path)?.file_type().is_fifo() {
+        bail!("{} is not a named pipe", path.display());
+    }
+    let fifo = OpenOptions::new().read(true).write(true).open(path)?;
+    let root = root.to_path_buf();
+    debug!("waiting for triggers on {:?}", path);
+    thread::spawn(move || read_triggers(fifo, &root, &sender));
+    Ok(())
+}
+
+/// Reads triggers from connections to the Unix socket.
+fn listen_socket(path: &Path, root: &Path, sender: Sender<Trigger>) -> Result<()> {
+    let listener = daemon::bind(path)?;
+    let root = root.to_path_buf();
+    debug!("waiting for triggers on {:?}", path);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let (root, sender) = (root.clone(), sender.clone());
+                    thread::spawn(move || read_triggers(stream, &root, &sender));
+                }
+                Err(e) => eprintln!("Error: cannot accept connection: {e:?}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Every line is a trigger with the changed file, relative to the workspace root or absolute, or
+/// a trigger of all tasks when it's empty. Files outside the workspace and JSON requests meant for
+/// the daemon are dropped.
+fn read_triggers<R: Read>(read: R, root: &Path, sender: &Sender<Trigger>) {
+    for line in BufReader::new(read).lines().map_while(Result::ok) {
+        let line = line.trim();
+        let trigger = if line.is_empty() {
+            Trigger::All
+        } else if line.starts_with('{') {
+            eprintln!("Error: {line} is a daemon request, not a changed file, skipping");
+            continue;
+        } else {
+            let path = root.join(line);
+            let inside = path
+                .strip_prefix(root)
+                .is_ok_and(|p| p.components().all(|c| c != Component::ParentDir));
+            if !inside {
+                eprintln!("Error: {line} is outside of the workspace, skipping");
+                continue;
+            }
+            Trigger::Paths(vec![path])
+        };
+        if sender.send(trigger).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Source, Watch, Watcher};
+    use crate::daemon;
+    use crate::testutils::init_logger;
+    use crate::Result;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_changes_of_ignored_files_are_skipped() -> Result<()> {
+        init_logger();
+        let root = std::env::temp_dir().join("blinc-watch-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root)?;
+        fs::write(root.join(".gitignore"), "ignored.txt\n")?;
+        fs::create_dir_all(root.join(".git/info"))?;
+        fs::write(root.join(".git/info/exclude"), "excluded.txt\n")?;
+        fs::create_dir_all(root.join("member/generated"))?;
+        fs::write(root.join("member/.gitignore"), "generated/\n")?;
+        let watch: Watch = toml::from_str(r#"ignore = ["*.log"]"#)?;
+        let watcher = Watcher::new(&root, &watch, &Source::Files)?;
+        let dir = root.canonicalize()?;
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(dir.join("ignored.txt"), "ignored").unwrap();
+            fs::write(dir.join("build.log"), "ignored").unwrap();
+            fs::write(dir.join("excluded.txt"), "ignored").unwrap();
+            fs::write(dir.join("member/generated/out.rs"), "ignored").unwrap();
+            fs::write(dir.join("lib.rs"), "changed").unwrap();
+            fs::write(dir.join("lib.rs"), "changed again").unwrap();
+        });
+        let changed = watcher.changes()?;
+        writer.join().unwrap();
+        assert_eq!(changed, Some(vec![root.canonicalize()?.join("lib.rs")]));
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_results_are_not_tracked_by_default() -> Result<()> {
+        assert!(!Watch::DEFAULT.stale());
+        let watch: Watch = toml::from_str("stale = true")?;
+        assert!(watch.stale());
+        Ok(())
+    }
+
+    #[test]
+    fn test_triggers_are_read_from_fifo() -> Result<()> {
+        init_logger();
+        let root = std::env::temp_dir().join("blinc-fifo-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root)?;
+        let fifo = root.join("trigger");
+        let watch: Watch = toml::from_str(r#"debounce = "50ms""#)?;
+        let watcher = Watcher::new(&root, &watch, &Source::Fifo(fifo.clone()))?;
+        let mut writer = OpenOptions::new().write(true).open(&fifo)?;
+        writeln!(writer, "/tmp/scratch.rs")?;
+        writeln!(writer, "../outside.rs")?;
+        writeln!(writer, "src/lib.rs")?;
+        let changed = watcher.changes()?;
+        assert_eq!(changed, Some(vec![root.canonicalize()?.join("src/lib.rs")]));
+        writeln!(writer)?;
+        assert_eq!(watcher.changes()?, None);
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_daemon_requests_sent_to_trigger_socket_are_dropped() -> Result<()> {
+        init_logger();
+        let root = std::env::temp_dir().join("blinc-socket-test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root)?;
+        let socket = root.join("trigger.sock");
+        let watch: Watch = toml::from_str(r#"debounce = "50ms""#)?;
+        let watcher = Watcher::new(&root, &watch, &Source::Socket(socket.clone()))?;
+        assert!(daemon::acknowledge(&socket).is_err());
+        writeln!(UnixStream::connect(&socket)?, "src/lib.rs")?;
+        let changed = watcher.changes()?;
+        assert_eq!(changed, Some(vec![root.canonicalize()?.join("src/lib.rs")]));
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
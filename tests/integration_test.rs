@@ -201,6 +201,61 @@ fn test_env_variables_are_set() {
     fs::remove_file(".blinc-config").unwrap();
 }
 
+#[test]
+#[serial]
+fn test_run_unknown_task() {
+    init_logger();
+    create_config(
+        r#"
+        [[task]]
+        name = "version"
+        cmd = "cargo"
+        args = ["--version"]
+
+        [colors]
+        pending = ["blue", "blank"]
+        failure = "red"
+        success = "green"
+        "#,
+        ".blinc-config",
+    );
+
+    let mut cmd = Command::cargo_bin("cargo-blinc").unwrap();
+    cmd.arg("blinc")
+        .arg("--config")
+        .arg(".blinc-config")
+        .arg("run")
+        .arg("unknown")
+        .assert()
+        .code(1)
+        .stderr(contains("unknown task `unknown`"));
+
+    fs::remove_file(".blinc-config").unwrap();
+}
+
+#[test]
+#[serial]
+fn test_invalid_config_exit_code() {
+    init_logger();
+    create_config(
+        r#"
+        [[task]]
+        args = ["--version"]
+        "#,
+        ".blinc-config",
+    );
+
+    let mut cmd = Command::cargo_bin("cargo-blinc").unwrap();
+    cmd.arg("blinc")
+        .arg("--config")
+        .arg(".blinc-config")
+        .assert()
+        .code(78)
+        .stderr(contains("invalid configuration"));
+
+    fs::remove_file(".blinc-config").unwrap();
+}
+
 fn create_config<I: Into<String>, A: AsRef<Path>>(config_content: I, path: A) {
     let config_content = config_content.into();
     let config_content: String = config_content.replace('\t', "");